@@ -0,0 +1,52 @@
+use std::fmt::Debug;
+
+/// Source of the process currently owning the foreground window.
+pub trait ForegroundSource: Debug {
+    /// Executable name of the foreground process, like `game.exe`.
+    fn process_name(&self) -> Option<String>;
+}
+
+/// Queries the foreground window of the desktop.
+#[derive(Debug, Default)]
+pub struct SystemForeground;
+
+#[cfg(windows)]
+impl ForegroundSource for SystemForeground {
+    fn process_name(&self) -> Option<String> {
+        use winapi::um::{
+            handleapi::CloseHandle,
+            processthreadsapi::OpenProcess,
+            winbase::QueryFullProcessImageNameW,
+            winnt::PROCESS_QUERY_LIMITED_INFORMATION,
+            winuser::{GetForegroundWindow, GetWindowThreadProcessId},
+        };
+        unsafe {
+            let hwnd = GetForegroundWindow();
+            if hwnd.is_null() {
+                return None;
+            }
+            let mut pid = 0;
+            GetWindowThreadProcessId(hwnd, &mut pid);
+            let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if handle.is_null() {
+                return None;
+            }
+            let mut buf = [0u16; 260];
+            let mut len = buf.len() as u32;
+            let ok = QueryFullProcessImageNameW(handle, 0, buf.as_mut_ptr(), &mut len);
+            CloseHandle(handle);
+            if ok == 0 {
+                return None;
+            }
+            let path = String::from_utf16_lossy(&buf[..len as usize]);
+            path.rsplit('\\').next().map(str::to_string)
+        }
+    }
+}
+
+#[cfg(not(windows))]
+impl ForegroundSource for SystemForeground {
+    fn process_name(&self) -> Option<String> {
+        None
+    }
+}
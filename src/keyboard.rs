@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// How a key is simulated on playback.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum KeyBackend {
+    /// `rdev::simulate` by virtual key codes.
+    #[default]
+    Rdev,
+    /// Hardware scan codes through the key injector, `SendInput` on Windows.
+    SendInput,
+}
+
+/// Sends a batch of keyboard events at once.
+pub trait KeyInjector: Debug + Send {
+    /// Send the keys, pressed if true, in order. Returns the number of events sent.
+    fn send(&mut self, keys: &[(rdev::Key, bool)]) -> usize;
+}
+
+/// Sends the keys as hardware scan codes in a single `SendInput` call,
+/// so that the keys of one record are truly simultaneous.
+#[derive(Debug, Default)]
+pub struct SendInputBatch;
+
+#[cfg(windows)]
+impl KeyInjector for SendInputBatch {
+    fn send(&mut self, keys: &[(rdev::Key, bool)]) -> usize {
+        use winapi::um::winuser::{
+            MapVirtualKeyW, SendInput, INPUT, INPUT_KEYBOARD, KEYBDINPUT, KEYEVENTF_EXTENDEDKEY,
+            KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE, MAPVK_VK_TO_VSC_EX,
+        };
+        let mut inputs: Vec<INPUT> = keys
+            .iter()
+            .filter_map(|&(key, press)| {
+                let vk = vk_code(key)?;
+                let scan = unsafe { MapVirtualKeyW(vk as u32, MAPVK_VK_TO_VSC_EX) };
+                if scan == 0 {
+                    return None;
+                }
+                let mut flags = KEYEVENTF_SCANCODE;
+                if scan & 0xFF00 == 0xE000 {
+                    flags |= KEYEVENTF_EXTENDEDKEY;
+                }
+                if !press {
+                    flags |= KEYEVENTF_KEYUP;
+                }
+                let mut input = INPUT {
+                    type_: INPUT_KEYBOARD,
+                    u: unsafe { std::mem::zeroed() },
+                };
+                unsafe {
+                    *input.u.ki_mut() = KEYBDINPUT {
+                        wVk: 0,
+                        wScan: (scan & 0xFF) as u16,
+                        dwFlags: flags,
+                        time: 0,
+                        dwExtraInfo: 0,
+                    };
+                }
+                Some(input)
+            })
+            .collect();
+        if inputs.is_empty() {
+            return 0;
+        }
+        unsafe {
+            SendInput(
+                inputs.len() as u32,
+                inputs.as_mut_ptr(),
+                std::mem::size_of::<INPUT>() as i32,
+            ) as usize
+        }
+    }
+}
+
+/// No batching outside of Windows, the keys are simulated one by one.
+#[cfg(not(windows))]
+impl KeyInjector for SendInputBatch {
+    fn send(&mut self, keys: &[(rdev::Key, bool)]) -> usize {
+        RdevKeys.send(keys)
+    }
+}
+
+/// Simulates the keys one by one with `rdev::simulate`, by virtual key codes.
+#[derive(Debug, Default)]
+pub struct RdevKeys;
+
+impl KeyInjector for RdevKeys {
+    fn send(&mut self, keys: &[(rdev::Key, bool)]) -> usize {
+        keys.iter()
+            .map(|&(key, press)| match press {
+                true => rdev::EventType::KeyPress(key),
+                false => rdev::EventType::KeyRelease(key),
+            })
+            .filter(|event| rdev::simulate(event).is_ok())
+            .count()
+    }
+}
+
+/// Windows virtual key code of the key, the same as used by `rdev`.
+#[cfg(windows)]
+fn vk_code(key: rdev::Key) -> Option<u16> {
+    use rdev::Key::*;
+    let vk = match key {
+        Alt => 164,
+        AltGr => 165,
+        Backspace => 0x08,
+        CapsLock => 20,
+        ControlLeft => 162,
+        ControlRight => 163,
+        Delete => 46,
+        DownArrow => 40,
+        End => 35,
+        Escape => 27,
+        F1 => 112,
+        F2 => 113,
+        F3 => 114,
+        F4 => 115,
+        F5 => 116,
+        F6 => 117,
+        F7 => 118,
+        F8 => 119,
+        F9 => 120,
+        F10 => 121,
+        F11 => 122,
+        F12 => 123,
+        Home => 36,
+        LeftArrow => 37,
+        MetaLeft => 91,
+        MetaRight => 92,
+        PageDown => 34,
+        PageUp => 33,
+        Return => 0x0D,
+        RightArrow => 39,
+        ShiftLeft => 160,
+        ShiftRight => 161,
+        Space => 32,
+        Tab => 0x09,
+        UpArrow => 38,
+        PrintScreen => 44,
+        ScrollLock => 145,
+        Pause => 19,
+        NumLock => 144,
+        BackQuote => 192,
+        Num1 => 49,
+        Num2 => 50,
+        Num3 => 51,
+        Num4 => 52,
+        Num5 => 53,
+        Num6 => 54,
+        Num7 => 55,
+        Num8 => 56,
+        Num9 => 57,
+        Num0 => 48,
+        Minus => 189,
+        Equal => 187,
+        KeyQ => 81,
+        KeyW => 87,
+        KeyE => 69,
+        KeyR => 82,
+        KeyT => 84,
+        KeyY => 89,
+        KeyU => 85,
+        KeyI => 73,
+        KeyO => 79,
+        KeyP => 80,
+        LeftBracket => 219,
+        RightBracket => 221,
+        KeyA => 65,
+        KeyS => 83,
+        KeyD => 68,
+        KeyF => 70,
+        KeyG => 71,
+        KeyH => 72,
+        KeyJ => 74,
+        KeyK => 75,
+        KeyL => 76,
+        SemiColon => 186,
+        Quote => 222,
+        BackSlash => 220,
+        IntlBackslash => 226,
+        KeyZ => 90,
+        KeyX => 88,
+        KeyC => 67,
+        KeyV => 86,
+        KeyB => 66,
+        KeyN => 78,
+        KeyM => 77,
+        Comma => 188,
+        Dot => 190,
+        Slash => 191,
+        Insert => 45,
+        KpMinus => 109,
+        KpPlus => 107,
+        KpMultiply => 106,
+        KpDivide => 111,
+        Kp0 => 96,
+        Kp1 => 97,
+        Kp2 => 98,
+        Kp3 => 99,
+        Kp4 => 100,
+        Kp5 => 101,
+        Kp6 => 102,
+        Kp7 => 103,
+        Kp8 => 104,
+        Kp9 => 105,
+        KpDelete => 110,
+        Unknown(code) => return code.try_into().ok(),
+        _ => return None,
+    };
+    Some(vk)
+}
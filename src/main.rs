@@ -1,6 +1,10 @@
+mod foreground;
+mod keyboard;
+mod pad;
 mod player;
 mod recorder;
 mod state;
+mod trigger;
 use recorder::Recorder;
 
 // fn run_vigem() {
@@ -51,10 +55,14 @@ use recorder::Recorder;
 //     }
 // }
 
-#[cfg(windows)]
 fn main() {
+    // events stream as JSON lines on stdout, keep the log out of it
+    let stream_json = std::env::args().any(|arg| arg == "--stream-json");
     env_logger::builder()
-        .target(env_logger::Target::Stdout)
+        .target(match stream_json {
+            true => env_logger::Target::Stderr,
+            false => env_logger::Target::Stdout,
+        })
         .filter_level(log::LevelFilter::Warn)
         // .filter_level(log::LevelFilter::Info)
         // .filter_level(log::LevelFilter::Debug)
@@ -64,10 +72,18 @@ fn main() {
     let mut record = Recorder::from_file("config.yaml".to_string());
     // println!("{:#?}", record);
     record.save_to_file("config.yaml".to_string());
-    record.init();
+    if stream_json {
+        record.stream_json(Box::new(std::io::stdout()));
+    }
+    log::warn!("{}", record.self_test());
+    if let Err(e) = record.init() {
+        log::warn!("Cannot start: {e}");
+    }
     while record.is_ok() {
         record.listen();
         record.match_shortcuts();
+        record.match_triggers();
+        record.watch_foreground();
     }
 }
 
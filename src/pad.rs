@@ -0,0 +1,44 @@
+//! Reports of the virtual controller, from `vigem_client` with the `controller` feature
+//! on Windows. Elsewhere the same layout without a driver, so that controller records
+//! are still handled, only with nothing to send them to.
+
+#[cfg(all(windows, feature = "controller", test))]
+pub use vigem_client::XButtons;
+#[cfg(all(windows, feature = "controller"))]
+pub use vigem_client::{Error, XGamepad};
+
+#[cfg(not(all(windows, feature = "controller")))]
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct XButtons {
+    pub raw: u16,
+}
+
+#[cfg(not(all(windows, feature = "controller")))]
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct XGamepad {
+    pub buttons: XButtons,
+    pub left_trigger: u8,
+    pub right_trigger: u8,
+    pub thumb_lx: i16,
+    pub thumb_ly: i16,
+    pub thumb_rx: i16,
+    pub thumb_ry: i16,
+}
+
+#[cfg(not(all(windows, feature = "controller")))]
+#[allow(unused)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    NotPluggedIn,
+    TargetNotReady,
+}
+
+#[cfg(not(all(windows, feature = "controller")))]
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NotPluggedIn => write!(f, "virtual controller not plugged in"),
+            Error::TargetNotReady => write!(f, "virtual controller not ready"),
+        }
+    }
+}
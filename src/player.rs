@@ -1,10 +1,14 @@
+use crate::keyboard::{KeyBackend, KeyInjector, RdevKeys, SendInputBatch};
+use crate::pad::{self, XGamepad};
 use crate::recorder::RecordEntry;
 use crate::state::{AnyKey, AnyOffset};
 use log::{debug, warn};
 use rdev::EventType;
 use std::{
+    collections::VecDeque,
+    io::Write,
     sync::mpsc::{Receiver, Sender, TryRecvError},
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock},
     thread::JoinHandle,
 };
 
@@ -12,49 +16,300 @@ enum PlayerEvent {
     Start,
     Stop,
     Seek(usize),
+    SeekMs(f64),
+    SeekToMs(f64),
     Update(Vec<RecordEntry>),
+    Benchmark(Vec<RecordEntry>, Sender<BenchmarkResult>),
+    Options(PlaybackOptions),
+    KeyInjector(KeyBackend, Box<dyn KeyInjector>),
+    #[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+    Rumble(Box<dyn RumbleSink>),
+    EngineSink(Option<EngineSink>),
+    SetLoop(bool),
+}
+
+/// Options of how the player plays records.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaybackOptions {
+    /// Time to keep the state after the last record before stopping.
+    pub trailing_hold_ms: f64,
+    /// Send the keyboard events of a record all at once through the key injector.
+    pub batch_keyboard: bool,
+    /// Keys sent by another backend than the others, e.g. keys `rdev` mishandles.
+    pub key_backends: Vec<(AnyKey, KeyBackend)>,
+    /// Mouse moves faster than this are spread over time in smaller steps.
+    pub max_mouse_speed_px_per_ms: Option<f64>,
+    /// File to write every played action to, with the time it is played at.
+    pub playback_capture_path: Option<String>,
+    /// (ms, left motor, right motor, duration ms) rumbles played at the time in records,
+    /// sorted by time.
+    pub rumble_cues: Vec<(f64, u16, u16, u64)>,
+    /// Keys pressed only with the probability, their releases follow the presses.
+    pub key_probability: Vec<(AnyKey, f64)>,
+    /// Seed of the random skipping by `key_probability`, each playback starts from it.
+    pub random_seed: u64,
+    /// Controller buttons held longer than this are released, against missing releases.
+    pub max_button_hold_ms: Option<f64>,
+}
+
+impl PlaybackOptions {
+    /// backend of the key, its override or the default by `batch_keyboard`
+    fn key_backend(&self, key: &AnyKey) -> KeyBackend {
+        match self.key_backends.iter().find(|(k, _)| k == key) {
+            Some(&(_, backend)) => backend,
+            None if self.batch_keyboard => KeyBackend::SendInput,
+            None => KeyBackend::Rdev,
+        }
+    }
+}
+
+/// Rumble of the controllers held by the player, as feedback while playing.
+pub trait RumbleSink: std::fmt::Debug + Send {
+    fn rumble(&mut self, left: u16, right: u16);
+}
+
+/// An action of a played record, as pushed to an `EngineSink`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordedEvent {
+    Press(AnyKey),
+    Release(AnyKey),
+    Move(AnyOffset),
+}
+
+/// Played events and their time in records in ms, in the order they are played.
+pub type EventBuffer = Arc<Mutex<VecDeque<(f64, RecordedEvent)>>>;
+
+/// Playback into a shared buffer instead of simulating the input, for a host
+/// (e.g. a game engine running this crate in-process) draining it on its own thread.
+/// Controller input is pushed as well, without plugging in the virtual controller.
+#[derive(Debug, Clone, Default)]
+pub struct EngineSink(pub EventBuffer);
+
+impl EngineSink {
+    /// push the actions of the record played at ms, in the order `emit` plays them
+    fn push(&self, ms: f64, record: &RecordEntry) {
+        let mut buffer = self.0.lock().unwrap();
+        let pressed = record.pressed.iter().cloned().map(RecordedEvent::Press);
+        let released = record.released.iter().cloned().map(RecordedEvent::Release);
+        let moves = record.moves.iter().cloned().map(RecordedEvent::Move);
+        buffer.extend(pressed.chain(released).chain(moves).map(|e| (ms, e)));
+    }
+}
+
+/// Small seeded random generator (SplitMix64), reproducible across runs.
+#[derive(Debug, Default, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+    /// uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// The record with the presses of keys in rules skipped by their probability,
+/// and the releases of skipped keys, which are kept in `skipped` until released.
+/// A key pressed again while skipped stays skipped.
+fn vary(
+    record: &RecordEntry,
+    rules: &[(AnyKey, f64)],
+    rng: &mut Rng,
+    skipped: &mut Vec<AnyKey>,
+) -> RecordEntry {
+    let mut res = record.clone();
+    res.pressed.retain(|key| {
+        if skipped.contains(key) {
+            return false;
+        }
+        let Some(&(_, probability)) = rules.iter().find(|r| &r.0 == key) else {
+            return true;
+        };
+        let fires = rng.next_f64() < probability;
+        if !fires {
+            debug!("skip: {:?}", key);
+            skipped.push(key.clone());
+        }
+        fires
+    });
+    res.released
+        .retain(|key| match skipped.iter().position(|k| k == key) {
+            Some(i) => {
+                skipped.remove(i);
+                false
+            }
+            None => true,
+        });
+    res
+}
+
+/// Interval of the steps of a mouse move spread by the speed cap.
+const MOUSE_STEP_MS: f64 = 1000.0 / 60.0;
+
+/// Steps moving the mouse from `from` to `to` no faster than `max_speed` px/ms,
+/// as (delay in ms before the step, x, y). `elapsed_ms` is the time since the mouse was at `from`.
+/// It is a single step without delay if the move is slow enough.
+fn mouse_steps(
+    from: (f64, f64),
+    to: (f64, f64),
+    elapsed_ms: f64,
+    max_speed: f64,
+) -> Vec<(f64, f64, f64)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let distance = dx.hypot(dy);
+    if distance <= max_speed * elapsed_ms.max(0.0) || max_speed <= 0.0 {
+        return vec![(0.0, to.0, to.1)];
+    }
+    let n = (distance / (max_speed * MOUSE_STEP_MS)).ceil().max(1.0) as usize;
+    (1..=n)
+        .map(|i| {
+            let delay = match i {
+                1 => (MOUSE_STEP_MS - elapsed_ms.max(0.0)).max(0.0),
+                _ => MOUSE_STEP_MS,
+            };
+            let t = i as f64 / n as f64;
+            (delay, from.0 + dx * t, from.1 + dy * t)
+        })
+        .collect()
+}
+
+/// Result of playing records as fast as possible.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BenchmarkResult {
+    /// number of keys and offsets played
+    pub events: usize,
+    /// number of events failed to play
+    pub failed: usize,
+    pub elapsed_ms: f64,
+}
+
+#[allow(unused)]
+impl BenchmarkResult {
+    pub fn events_per_sec(&self) -> f64 {
+        self.events as f64 / (self.elapsed_ms / 1000.0)
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct RecordPlayer {
     pub current_pos: Arc<RwLock<usize>>,
     pub is_playing: Arc<RwLock<bool>>,
+    /// last time the player thread went through its loop
+    last_progress: Arc<RwLock<Option<std::time::Instant>>>,
+    /// number of times the player thread was restarted by the watchdog
+    pub restarts: usize,
 
     sender: Option<Sender<PlayerEvent>>,
     player: Option<JoinHandle<()>>,
+    /// target of the virtual controller of a new player thread
+    connect: Option<ConnectPad>,
+}
+
+/// Connects the target of the virtual controller, on the first controller input played.
+#[derive(Clone)]
+struct ConnectPad(Arc<dyn Fn() -> Result<Box<dyn PadTarget>, String> + Send + Sync>);
+
+impl std::fmt::Debug for ConnectPad {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ConnectPad")
+    }
+}
+
+/// Plug in a virtual controller on the ViGEmBus driver.
+#[cfg(all(windows, feature = "controller"))]
+fn connect_vigem() -> Result<Box<dyn PadTarget>, String> {
+    // Connect to the ViGEmBus driver
+    let client =
+        vigem_client::Client::connect().map_err(|e| format!("Cannot connect to ViGEmBus: {e}"))?;
+    // Create the virtual controller target
+    let id = vigem_client::TargetId::XBOX360_WIRED;
+    let mut target = vigem_client::Xbox360Wired::new(client, id);
+    // Plugin the virtual controller
+    target
+        .plugin()
+        .map_err(|e| format!("Cannot plug in virtual controller: {e}"))?;
+    // Wait for the virtual controller to be ready to accept updates
+    target
+        .wait_ready()
+        .map_err(|e| format!("Virtual controller not ready: {e}"))?;
+    Ok(Box::new(target))
+}
+
+/// No virtual controller without the `controller` feature on Windows.
+#[cfg(not(all(windows, feature = "controller")))]
+fn connect_vigem() -> Result<Box<dyn PadTarget>, String> {
+    Err("Built without virtual controller (the `controller` feature on Windows)".to_string())
+}
+
+/// Check the ViGEmBus driver by plugging in a virtual controller, unplugged right away.
+pub fn probe_vigem() -> Result<(), String> {
+    connect_vigem().map(|_| ())
 }
 
 impl RecordPlayer {
     pub fn new() -> Self {
         Default::default()
     }
-    pub fn init(&mut self) {
+    /// Start the player thread, the virtual controller is plugged in when first needed,
+    /// so keyboard and mouse playback work without ViGEmBus.
+    pub fn init(&mut self) -> Result<(), String> {
+        self.spawn(connect_vigem)
+    }
+    fn spawn(
+        &mut self,
+        connect: impl Fn() -> Result<Box<dyn PadTarget>, String> + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        self.spawn_with(ConnectPad(Arc::new(connect)))
+    }
+    fn spawn_with(&mut self, connect: ConnectPad) -> Result<(), String> {
         let (tx, rx) = std::sync::mpsc::channel();
         self.sender = Some(tx);
-
-        // Connect to the ViGEmBus driver
-        let client = vigem_client::Client::connect().unwrap();
-        // Create the virtual controller target
-        let id = vigem_client::TargetId::XBOX360_WIRED;
-        let mut target = vigem_client::Xbox360Wired::new(client, id);
-        // Plugin the virtual controller
-        target.plugin().unwrap();
-        // Wait for the virtual controller to be ready to accept updates
-        target.wait_ready().unwrap();
+        self.connect = Some(connect.clone());
+        *self.last_progress.write().unwrap() = Some(std::time::Instant::now());
 
         let mut player = Player {
             recv: rx,
             is_playing: self.is_playing.clone(),
             current_pos: self.current_pos.clone(),
+            last_progress: self.last_progress.clone(),
             records: Vec::new(),
             timer: std::time::Instant::now(),
             start_time: 0.0,
-            controller: Controller::new(target),
+            options: Default::default(),
+            last_mouse: None,
+            mouse_path: VecDeque::new(),
+            mouse_delay: 0.0,
+            capture: None,
+            rumble: None,
+            next_cue: 0,
+            rumble_until: None,
+            rng: Rng::default(),
+            skipped: Vec::new(),
+            looping: false,
+            held: Vec::new(),
+            loop_held: Vec::new(),
+            buttons_since: Vec::new(),
+            engine: None,
+            keyboard: Box::new(SendInputBatch),
+            rdev_keys: Box::new(RdevKeys),
+            connect,
+            no_controller: false,
+            controller: Controller::detached(),
         };
-        let th = std::thread::spawn(move || {
-            player.cycle();
-        });
+        let th = std::thread::Builder::new()
+            .name("player".to_string())
+            .spawn(move || {
+                player.cycle();
+            })
+            .map_err(|e| format!("Cannot start player: {e}"))?;
         self.player = Some(th);
+        Ok(())
     }
     pub fn get_progress(&self) -> usize {
         *self.current_pos.read().unwrap()
@@ -63,9 +318,34 @@ impl RecordPlayer {
         let sender = self.sender.as_ref().unwrap();
         sender.send(PlayerEvent::Seek(pos)).unwrap();
     }
+    /// move the playing position by delta_ms, keeping the pace of the following records.
+    pub fn seek_ms(&mut self, delta_ms: f64) {
+        let sender = self.sender.as_ref().unwrap();
+        sender.send(PlayerEvent::SeekMs(delta_ms)).unwrap();
+    }
+    /// move the playing position to the time of ms in records.
+    pub fn seek_to_ms(&mut self, ms: f64) {
+        let sender = self.sender.as_ref().unwrap();
+        sender.send(PlayerEvent::SeekToMs(ms)).unwrap();
+    }
     pub fn is_done(&self) -> bool {
         !*self.is_playing.read().unwrap()
     }
+    pub fn set_options(&mut self, options: PlaybackOptions) {
+        let sender = self.sender.as_ref().unwrap();
+        sender.send(PlayerEvent::Options(options)).unwrap();
+    }
+    /// Repeat the records from the start after the last one until stopped.
+    pub fn set_loop(&mut self, looping: bool) {
+        let sender = self.sender.as_ref().unwrap();
+        sender.send(PlayerEvent::SetLoop(looping)).unwrap();
+    }
+    /// Set the controllers to play the rumble cues on.
+    #[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+    pub fn set_rumble_sink(&mut self, rumble: Box<dyn RumbleSink>) {
+        let sender = self.sender.as_ref().unwrap();
+        sender.send(PlayerEvent::Rumble(rumble)).unwrap();
+    }
     pub fn start_playback(&mut self, records: &[RecordEntry]) {
         let sender = self.sender.as_ref().unwrap();
         sender.send(PlayerEvent::Update(records.to_vec())).unwrap();
@@ -77,6 +357,92 @@ impl RecordPlayer {
         let sender = self.sender.as_ref().unwrap();
         sender.send(PlayerEvent::Stop).unwrap();
     }
+    /// The player thread is not playing, or made progress within timeout_ms.
+    pub fn is_healthy(&self, timeout_ms: f64) -> bool {
+        if self.is_done() {
+            return true;
+        }
+        self.last_progress
+            .read()
+            .unwrap()
+            .is_none_or(|t| t.elapsed().as_secs_f64() * 1000.0 <= timeout_ms)
+    }
+    /// Replace a hung player thread by a new one, which is stopped at pos 0.
+    /// Returns true if restarted.
+    ///
+    /// The hung thread cannot be joined, it is left behind with its own shared state,
+    /// and exits once it wakes up. Options and input backends set before are not kept.
+    pub fn watchdog(&mut self, timeout_ms: f64) -> bool {
+        if self.is_healthy(timeout_ms) {
+            return false;
+        }
+        let Some(connect) = self.connect.clone() else {
+            return false;
+        };
+        warn!("Player made no progress for {timeout_ms}ms, restart it.");
+        self.sender = None;
+        self.player = None;
+        self.current_pos = Default::default();
+        self.is_playing = Default::default();
+        self.last_progress = Default::default();
+        self.restarts += 1;
+        if let Err(e) = self.spawn_with(connect) {
+            warn!("{e}");
+        }
+        true
+    }
+}
+
+#[allow(unused)]
+/// this is implement of playing without devices
+impl RecordPlayer {
+    /// Start the player thread with a virtual controller dropping its reports,
+    /// so that playing touches no real device.
+    pub fn init_without_pad(&mut self) -> Result<(), String> {
+        self.spawn(|| Ok(Box::new(NullTarget)))
+    }
+    /// The player thread is started by `init`.
+    pub fn is_started(&self) -> bool {
+        self.sender.is_some()
+    }
+}
+
+#[allow(unused)]
+/// this is implement of benchmarking the player
+impl RecordPlayer {
+    /// Play the records ignoring their timing, blocks until all are played.
+    ///
+    /// The records to playback and the playing position are kept.
+    pub fn benchmark_playback(&mut self, records: &[RecordEntry]) -> BenchmarkResult {
+        let sender = self.sender.as_ref().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        sender
+            .send(PlayerEvent::Benchmark(records.to_vec(), tx))
+            .unwrap();
+        rx.recv().unwrap()
+    }
+}
+
+#[allow(unused)]
+/// this is implement of replacing the input backends
+impl RecordPlayer {
+    /// Replace the backend of batched keyboard events.
+    pub fn set_key_injector(&mut self, keyboard: Box<dyn KeyInjector>) {
+        let sender = self.sender.as_ref().unwrap();
+        let event = PlayerEvent::KeyInjector(KeyBackend::SendInput, keyboard);
+        sender.send(event).unwrap();
+    }
+    /// Replace `rdev::simulate` for the keys of the `Rdev` backend.
+    pub fn set_rdev_injector(&mut self, keyboard: Box<dyn KeyInjector>) {
+        let sender = self.sender.as_ref().unwrap();
+        let event = PlayerEvent::KeyInjector(KeyBackend::Rdev, keyboard);
+        sender.send(event).unwrap();
+    }
+    /// Play into the buffer of the sink instead of simulating the input, or simulate again if None.
+    pub fn set_engine_sink(&mut self, engine: Option<EngineSink>) {
+        let sender = self.sender.as_ref().unwrap();
+        sender.send(PlayerEvent::EngineSink(engine)).unwrap();
+    }
 }
 
 /// private
@@ -84,17 +450,53 @@ struct Player {
     recv: Receiver<PlayerEvent>,
     is_playing: Arc<RwLock<bool>>,
     current_pos: Arc<RwLock<usize>>,
+    last_progress: Arc<RwLock<Option<std::time::Instant>>>,
     records: Vec<RecordEntry>,
     timer: std::time::Instant,
 
     start_time: f64,
+    options: PlaybackOptions,
+    /// last mouse position played and its time in records
+    last_mouse: Option<(f64, f64, f64)>,
+    /// steps of a mouse move under the speed cap, at their time in records
+    mouse_path: VecDeque<(f64, f64, f64)>,
+    /// time the record of the capped mouse move is delayed by, and the following ones after it
+    mouse_delay: f64,
+    /// file of the playback capture, opened on start
+    capture: Option<std::io::BufWriter<std::fs::File>>,
+    rumble: Option<Box<dyn RumbleSink>>,
+    /// index of the next rumble cue to play
+    next_cue: usize,
+    /// time in records when the current rumble ends
+    rumble_until: Option<f64>,
+    /// random skipping of `key_probability`
+    rng: Rng,
+    /// keys whose press was skipped, to skip their release
+    skipped: Vec<AnyKey>,
+    /// start over after the last record
+    looping: bool,
+    /// keys pressed and not released by the played records
+    held: Vec<AnyKey>,
+    /// keys held over from the end of the loop, not to press again at its start
+    loop_held: Vec<AnyKey>,
+    /// held controller buttons and the time in records they were pressed at
+    buttons_since: Vec<(AnyKey, f64)>,
+    /// played into the buffer of the host instead of simulated, if set
+    engine: Option<EngineSink>,
 
+    keyboard: Box<dyn KeyInjector>,
+    /// simulates the keys of the `Rdev` backend
+    rdev_keys: Box<dyn KeyInjector>,
+    connect: ConnectPad,
+    /// the virtual controller failed to connect in this playback, and is skipped
+    no_controller: bool,
     controller: Controller,
 }
 
 impl Player {
     fn cycle(&mut self) {
         loop {
+            *self.last_progress.write().unwrap() = Some(std::time::Instant::now());
             // process messages until empty
             let Some(is_empty) = self.process_msg() else {
                 break;
@@ -108,23 +510,50 @@ impl Player {
                 std::thread::sleep(std::time::Duration::from_millis(1000 / 60));
                 continue;
             }
+            let ms = self.timer.elapsed().as_secs_f64() * 1000.0 - self.start_time;
+            self.play_cues(ms);
+            self.release_stale_buttons(ms);
+            self.play_mouse_path(ms);
             // try get the record at current position to play
             let pos = *self.current_pos.read().unwrap();
             let Some(record) = self.records.get(pos) else {
+                // keep the state for the trailing hold after the last record
+                let last = self.records.last().map_or(0.0, |r| r.ms);
+                let dt = last + self.options.trailing_hold_ms - ms;
+                if dt > 0.1 {
+                    let dt = dt.min(1000.0 / 60.0).min(self.next_wake_ms() - ms);
+                    std::thread::sleep(std::time::Duration::from_secs_f64(dt / 1000.0));
+                    continue;
+                }
+                if self.looping && !self.records.is_empty() {
+                    self.restart_loop();
+                    continue;
+                }
                 self.stop();
                 continue;
             };
-            // sleep until next record time
-            let ms = self.timer.elapsed().as_secs_f64() * 1000.0 - self.start_time;
-            let dt = record.ms - ms;
-            std::thread::sleep(std::time::Duration::from_secs_f64(dt.max(0.1) / 1000.0));
-            // play the record
+            // sleep until next record time, in short steps to keep processing messages
+            let dt = record.ms + self.mouse_delay - ms;
+            if dt > 0.1 {
+                let dt = dt.min(1000.0 / 60.0).min(self.next_wake_ms() - ms);
+                std::thread::sleep(std::time::Duration::from_secs_f64(dt / 1000.0));
+                continue;
+            }
+            // a capped mouse move delays the record, its steps are played meanwhile
+            if let Some(max_speed) = self.options.max_mouse_speed_px_per_ms {
+                if self.limit_mouse_speed(pos, max_speed, ms) {
+                    continue;
+                }
+            }
+            self.play_mouse_path(f64::INFINITY);
+            // play the record, an explicit wait delays the following records
             self.play(pos);
+            self.start_time += std::mem::take(&mut self.mouse_delay);
+            if let Some(wait) = self.records[pos].wait {
+                self.start_time += wait;
+            }
             // move pos to next
             *self.current_pos.write().unwrap() = pos + 1;
-            if pos + 1 >= self.records.len() {
-                self.stop();
-            }
         }
         self.stop();
     }
@@ -136,21 +565,128 @@ impl Player {
         );
         *self.is_playing.write().unwrap() = true;
         self.start_time = self.timer.elapsed().as_secs_f64() * 1000.0;
+        self.next_cue = 0;
+        self.rng = Rng(self.options.random_seed);
+        self.skipped.clear();
+        self.held.clear();
+        self.loop_held.clear();
+        self.buttons_since.clear();
+        self.clear_mouse_path();
+        self.no_controller = false;
+        // a clean baseline, whatever a prior playback or benchmark left on the pad
+        self.controller.neutral();
+        if self.controller.target.is_some() {
+            if let Err(e) = self.controller.try_update() {
+                warn!("Failed to flush controller: {e}");
+            }
+        }
+        self.capture = self
+            .options
+            .playback_capture_path
+            .as_ref()
+            .and_then(|path| {
+                std::fs::File::create(path)
+                    .inspect_err(|e| warn!("Cannot create playback capture {path}: {e}"))
+                    .ok()
+                    .map(std::io::BufWriter::new)
+            });
     }
     fn stop(&mut self) {
         warn!(
             "Player stops at pos: {:?}",
             *self.current_pos.read().unwrap()
         );
+        if let Some(mut capture) = self.capture.take() {
+            if let Err(e) = capture.flush() {
+                warn!("Cannot write playback capture: {e}");
+            }
+        }
+        if let (Some(rumble), Some(_)) = (&mut self.rumble, self.rumble_until.take()) {
+            rumble.rumble(0, 0);
+        }
+        // nothing stays stuck down after playing
+        let held = std::mem::take(&mut self.held);
+        self.loop_held.clear();
+        self.buttons_since.clear();
+        self.clear_mouse_path();
+        self.release_held(held);
+        self.controller.reset();
+        if let Err(e) = self.controller.try_update() {
+            warn!("Failed to reset controller: {e}");
+        }
         *self.is_playing.write().unwrap() = false;
     }
+    /// Start the records over. Keys held at the end and pressed by the first record
+    /// stay held, the other held keys are released.
+    fn restart_loop(&mut self) {
+        warn!("Player loops back to start.");
+        let first = &self.records[0].pressed;
+        let (kept, released): (Vec<_>, Vec<_>) =
+            self.held.drain(..).partition(|k| first.contains(k));
+        self.release_held(released);
+        self.held = kept.clone();
+        self.loop_held = kept;
+        // the kept buttons are held since the new start
+        for (_, since) in self.buttons_since.iter_mut() {
+            *since = 0.0;
+        }
+        self.buttons_since.retain(|(k, _)| self.held.contains(k));
+        self.next_cue = 0;
+        self.last_mouse = None;
+        self.clear_mouse_path();
+        *self.current_pos.write().unwrap() = 0;
+        self.start_time = self.timer.elapsed().as_secs_f64() * 1000.0;
+    }
+    /// Release the keys the way records are played.
+    fn release_held(&mut self, keys: Vec<AnyKey>) {
+        if keys.is_empty() {
+            return;
+        }
+        debug!("release held: {:?}", keys);
+        let release = RecordEntry {
+            ms: 0.0,
+            released: keys,
+            ..Default::default()
+        };
+        if let Some(engine) = &self.engine {
+            let ms = self.timer.elapsed().as_secs_f64() * 1000.0 - self.start_time;
+            engine.push(ms, &release);
+            return;
+        }
+        let failed = Self::emit(
+            &release,
+            &mut self.controller,
+            self.keyboard.as_mut(),
+            self.rdev_keys.as_mut(),
+            &self.options,
+        );
+        if failed > 0 {
+            warn!("Failed to release {failed} held keys");
+        }
+    }
     fn seek(&mut self, pos: usize) {
         warn!("Player pos seeks to: {:?}", pos);
+        self.clear_mouse_path();
         *self.current_pos.write().unwrap() = pos;
     }
+    fn seek_ms(&mut self, delta_ms: f64) {
+        let now = self.timer.elapsed().as_secs_f64() * 1000.0;
+        self.seek_to_ms(now - self.start_time + delta_ms);
+    }
+    fn seek_to_ms(&mut self, ms: f64) {
+        let now = self.timer.elapsed().as_secs_f64() * 1000.0;
+        let ms = ms.max(0.0);
+        let pos = self.records.partition_point(|r| r.ms < ms);
+        self.next_cue = self.options.rumble_cues.partition_point(|c| c.0 < ms);
+        warn!("Player seeks to: {:.2}ms at pos: {:?}", ms, pos);
+        self.clear_mouse_path();
+        *self.current_pos.write().unwrap() = pos;
+        self.start_time = now - ms;
+    }
     fn update(&mut self, records: Vec<RecordEntry>) {
         warn!("Player set records: {:?}", records.len());
         self.records = records;
+        self.last_mouse = None;
         self.seek(0);
     }
     fn process_msg(&mut self) -> Option<bool> {
@@ -158,27 +694,314 @@ impl Player {
             Ok(PlayerEvent::Start) => self.start(),
             Ok(PlayerEvent::Stop) => self.stop(),
             Ok(PlayerEvent::Seek(pos)) => self.seek(pos),
+            Ok(PlayerEvent::SeekMs(ms)) => self.seek_ms(ms),
+            Ok(PlayerEvent::SeekToMs(ms)) => self.seek_to_ms(ms),
             Ok(PlayerEvent::Update(records)) => self.update(records),
+            Ok(PlayerEvent::Options(options)) => self.options = options,
+            Ok(PlayerEvent::KeyInjector(KeyBackend::SendInput, keyboard)) => {
+                self.keyboard = keyboard
+            }
+            Ok(PlayerEvent::KeyInjector(KeyBackend::Rdev, keyboard)) => self.rdev_keys = keyboard,
+            Ok(PlayerEvent::Rumble(rumble)) => self.rumble = Some(rumble),
+            Ok(PlayerEvent::EngineSink(engine)) => self.engine = engine,
+            Ok(PlayerEvent::SetLoop(looping)) => self.looping = looping,
+            Ok(PlayerEvent::Benchmark(records, tx)) => {
+                let _ = tx.send(self.benchmark(&records));
+            }
             Err(TryRecvError::Empty) => return Some(true), // nothing, continue playing
             Err(TryRecvError::Disconnected) => return None, // stop playing
         }
         Some(false) // maybe not empty
     }
 
+    /// start the rumble cues due at ms in records, and stop the rumble when it ends
+    fn play_cues(&mut self, ms: f64) {
+        let Some(rumble) = &mut self.rumble else {
+            return;
+        };
+        if self.rumble_until.is_some_and(|until| ms >= until) {
+            rumble.rumble(0, 0);
+            self.rumble_until = None;
+        }
+        while let Some(&(at, left, right, duration)) = self.options.rumble_cues.get(self.next_cue) {
+            if at > ms {
+                break;
+            }
+            debug!("rumble: ({left}, {right}) for {duration}ms at {at}ms");
+            rumble.rumble(left, right);
+            self.rumble_until = Some(at + duration as f64);
+            self.next_cue += 1;
+        }
+    }
+    /// release the controller buttons held longer than `max_button_hold_ms` at ms in records
+    fn release_stale_buttons(&mut self, ms: f64) {
+        let Some(max_hold) = self.options.max_button_hold_ms else {
+            return;
+        };
+        let (stale, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.buttons_since)
+            .into_iter()
+            .partition(|(_, since)| ms - since >= max_hold);
+        self.buttons_since = kept;
+        if stale.is_empty() {
+            return;
+        }
+        let keys: Vec<_> = stale.into_iter().map(|(k, _)| k).collect();
+        warn!("Release {keys:?} held over {max_hold}ms without release");
+        self.held.retain(|k| !keys.contains(k));
+        self.release_held(keys);
+    }
+    /// time in records to wake up for the next rumble change, button release or mouse step
+    fn next_wake_ms(&self) -> f64 {
+        let release = match self.options.max_button_hold_ms {
+            Some(max_hold) => self
+                .buttons_since
+                .iter()
+                .map(|(_, since)| since + max_hold)
+                .fold(f64::INFINITY, f64::min),
+            None => f64::INFINITY,
+        };
+        let step = self.mouse_path.front().map_or(f64::INFINITY, |s| s.0);
+        self.next_cue_ms().min(release).min(step)
+    }
+    /// time in records of the next rumble change, to wake up for
+    fn next_cue_ms(&self) -> f64 {
+        if self.rumble.is_none() {
+            return f64::INFINITY;
+        }
+        let next = self
+            .options
+            .rumble_cues
+            .get(self.next_cue)
+            .map_or(f64::INFINITY, |c| c.0);
+        next.min(self.rumble_until.unwrap_or(f64::INFINITY))
+    }
     fn play(&mut self, pos: usize) {
+        let mut varied = match self.options.key_probability.is_empty() {
+            true => None,
+            false => Some(vary(
+                &self.records[pos],
+                &self.options.key_probability,
+                &mut self.rng,
+                &mut self.skipped,
+            )),
+        };
+        if !self.loop_held.is_empty() {
+            let kept = std::mem::take(&mut self.loop_held);
+            let mut record = varied.unwrap_or_else(|| self.records[pos].clone());
+            record.pressed.retain(|k| !kept.contains(k));
+            varied = Some(record);
+        }
+        if self.records[pos].moves.iter().any(is_wheel) {
+            let record = varied.as_ref().unwrap_or(&self.records[pos]);
+            varied = Some(per_notch(record));
+        }
+        if self.engine.is_none()
+            && has_controller_input(varied.as_ref().unwrap_or(&self.records[pos]))
+            && !self.connect_controller()
+        {
+            varied = Some(without_controller_input(
+                varied.as_ref().unwrap_or(&self.records[pos]),
+            ));
+        }
+        let record = varied.as_ref().unwrap_or(&self.records[pos]);
+        let ms = self.timer.elapsed().as_secs_f64() * 1000.0 - self.start_time;
+        for key in &record.pressed {
+            if !self.held.contains(key) {
+                self.held.push(key.clone());
+                if matches!(key, AnyKey::Controller(..)) {
+                    self.buttons_since.push((key.clone(), ms));
+                }
+            }
+        }
+        self.held.retain(|k| !record.released.contains(k));
+        self.buttons_since
+            .retain(|(k, _)| !record.released.contains(k));
+        if let Some(capture) = &mut self.capture {
+            if let Err(e) = Self::capture(capture, ms, record) {
+                warn!("Cannot write playback capture: {e}");
+            }
+        }
+        if let Some(engine) = &self.engine {
+            engine.push(ms, record);
+            return;
+        }
+        let failed = Self::emit(
+            record,
+            &mut self.controller,
+            self.keyboard.as_mut(),
+            self.rdev_keys.as_mut(),
+            &self.options,
+        );
+        if failed > 0 {
+            warn!("Failed to play {failed} events at pos: {pos}");
+        }
+    }
+    /// Queue the steps towards the first mouse move of the record under the speed cap, from ms
+    /// in records. The record and the following ones are delayed by the time taken, returns
+    /// true if delayed. The record is due again after the steps, then without delay.
+    fn limit_mouse_speed(&mut self, pos: usize, max_speed: f64, ms: f64) -> bool {
         let record = &self.records[pos];
-        let mut controller = &mut self.controller;
-        // play the record
+        let Some((x, y)) = record.moves.iter().find_map(|m| match *m {
+            AnyOffset::Mouse(x, y) => Some((x, y)),
+            _ => None,
+        }) else {
+            return false;
+        };
+        let Some((last_x, last_y, last_ms)) = self.last_mouse.replace((x, y, record.ms)) else {
+            return false;
+        };
+        let steps = mouse_steps((last_x, last_y), (x, y), record.ms - last_ms, max_speed);
+        let delay: f64 = steps.iter().map(|s| s.0).sum();
+        if delay <= 0.0 {
+            return false;
+        }
+        self.mouse_delay = delay;
+        // the last step is played with the record
+        let mut at = ms;
+        for &(dt, x, y) in &steps[..steps.len() - 1] {
+            at += dt;
+            self.mouse_path.push_back((at, x, y));
+        }
+        true
+    }
+    fn clear_mouse_path(&mut self) {
+        self.mouse_path.clear();
+        self.mouse_delay = 0.0;
+    }
+    /// play the steps of the capped mouse move due at ms in records
+    fn play_mouse_path(&mut self, ms: f64) {
+        while let Some(&(at, x, y)) = self.mouse_path.front() {
+            if at > ms {
+                break;
+            }
+            self.mouse_path.pop_front();
+            if let Some(engine) = &self.engine {
+                let ms = self.timer.elapsed().as_secs_f64() * 1000.0 - self.start_time;
+                let mut buffer = engine.0.lock().unwrap();
+                buffer.push_back((ms, RecordedEvent::Move(AnyOffset::Mouse(x, y))));
+            } else if rdev::simulate(&EventType::MouseMove { x, y }).is_err() {
+                warn!("Failed to move mouse to ({x}, {y})");
+            }
+        }
+    }
+    /// write the actions of the record, one per line, in the order they are played
+    fn capture(capture: &mut impl Write, ms: f64, record: &RecordEntry) -> std::io::Result<()> {
         for key in &record.pressed {
-            Self::press(key, &mut controller).unwrap();
+            writeln!(capture, "{ms:.3} press {key:?}")?;
         }
         for key in &record.released {
-            Self::release(key, &mut controller).unwrap();
+            writeln!(capture, "{ms:.3} release {key:?}")?;
         }
         for offset in &record.moves {
-            Self::moves(offset, &mut controller).unwrap();
+            writeln!(capture, "{ms:.3} move {offset:?}")?;
+        }
+        Ok(())
+    }
+    /// Plug in the virtual controller if not yet, false if it is not available.
+    /// A failure is warned once per playback.
+    fn connect_controller(&mut self) -> bool {
+        if self.controller.target.is_some() {
+            return true;
+        }
+        if self.no_controller {
+            return false;
+        }
+        match (self.connect.0)() {
+            Ok(target) => {
+                self.controller.attach(target);
+                true
+            }
+            Err(e) => {
+                warn!("{e}, controller input is skipped.");
+                self.no_controller = true;
+                false
+            }
+        }
+    }
+    fn benchmark(&mut self, records: &[RecordEntry]) -> BenchmarkResult {
+        warn!("Player benchmark records: {:?}", records.len());
+        if records.iter().any(has_controller_input) {
+            self.connect_controller();
+        }
+        let start = self.timer.elapsed().as_secs_f64() * 1000.0;
+        let mut res = BenchmarkResult::default();
+        for record in records {
+            res.events += record.pressed.len() + record.released.len() + record.moves.len();
+            res.failed += Self::emit(
+                record,
+                &mut self.controller,
+                self.keyboard.as_mut(),
+                self.rdev_keys.as_mut(),
+                &self.options,
+            );
+        }
+        res.elapsed_ms = self.timer.elapsed().as_secs_f64() * 1000.0 - start;
+        warn!("Player benchmark: {:?}", res);
+        res
+    }
+    /// play the record, returns the number of events failed.
+    /// The keyboard events go in order to the injector of their backend, those of a row of
+    /// keys with the same backend in one batch, the ones of `SendInput` in a single call.
+    fn emit(
+        record: &RecordEntry,
+        controller: &mut Controller,
+        keyboard: &mut dyn KeyInjector,
+        rdev_keys: &mut dyn KeyInjector,
+        options: &PlaybackOptions,
+    ) -> usize {
+        let mut failed = 0;
+        let mut batch = Vec::new();
+        let keys = record.pressed.iter().map(|k| (k, true));
+        for (key, press) in keys.chain(record.released.iter().map(|k| (k, false))) {
+            match key {
+                AnyKey::Keyboard(k) => batch.push((options.key_backend(key), k.rdev_key(), press)),
+                // simulated by rdev as well, after the keys before
+                AnyKey::MouseButton(_) => {
+                    failed += Self::send_keys(&mut batch, keyboard, rdev_keys);
+                    failed += Self::press_or_release(key, press, controller).is_err() as usize;
+                }
+                AnyKey::Controller(..) => {
+                    failed += Self::press_or_release(key, press, controller).is_err() as usize;
+                }
+            }
+        }
+        failed += Self::send_keys(&mut batch, keyboard, rdev_keys);
+        for offset in &record.moves {
+            failed += Self::moves(offset, controller).is_err() as usize;
+        }
+        if let Err(e) = controller.try_update() {
+            warn!("Failed to update controller: {e}");
+            failed += 1;
+        }
+        failed
+    }
+    /// Send the batched keys in order, returns the number failed.
+    fn send_keys(
+        batch: &mut Vec<(KeyBackend, rdev::Key, bool)>,
+        keyboard: &mut dyn KeyInjector,
+        rdev_keys: &mut dyn KeyInjector,
+    ) -> usize {
+        let mut failed = 0;
+        for run in batch.chunk_by(|a, b| a.0 == b.0) {
+            let keys: Vec<_> = run.iter().map(|&(_, key, press)| (key, press)).collect();
+            let sent = match run[0].0 {
+                KeyBackend::SendInput => keyboard.send(&keys),
+                KeyBackend::Rdev => rdev_keys.send(&keys),
+            };
+            failed += keys.len().saturating_sub(sent);
+        }
+        batch.clear();
+        failed
+    }
+    fn press_or_release(
+        key: &AnyKey,
+        press: bool,
+        controller: &mut Controller,
+    ) -> Result<(), rdev::SimulateError> {
+        match press {
+            true => Self::press(key, controller),
+            false => Self::release(key, controller),
         }
-        self.controller.try_update();
     }
     fn to_btn(btn: u32, press: bool) -> EventType {
         let btn = match btn {
@@ -213,39 +1036,163 @@ impl Player {
         debug!("move: {:?}", offset);
         match *offset {
             AnyOffset::Mouse(x, y) => rdev::simulate(&EventType::MouseMove { x, y }),
+            AnyOffset::MouseDelta(dx, dy) => move_relative(dx.round() as i32, dy.round() as i32),
             AnyOffset::Wheel(dx, dy) => rdev::simulate(&EventType::Wheel {
                 delta_x: dx as i64,
                 delta_y: dy as i64,
             }),
             AnyOffset::Trigger(_, l, r) => Ok(controller.trigger(l, r)),
+            AnyOffset::LeftTrigger(_, l) => {
+                controller.trigger(l, controller.right_trigger());
+                Ok(())
+            }
+            AnyOffset::RightTrigger(_, r) => {
+                controller.trigger(controller.left_trigger(), r);
+                Ok(())
+            }
             AnyOffset::LeftStick(_, x, y) => Ok(controller.left_stick(x, y)),
             AnyOffset::RightStick(_, x, y) => Ok(controller.right_stick(x, y)),
         }
     }
 }
 
+fn is_controller_offset(offset: &AnyOffset) -> bool {
+    !matches!(
+        offset,
+        AnyOffset::Mouse(..) | AnyOffset::MouseDelta(..) | AnyOffset::Wheel(..)
+    )
+}
+
+fn has_controller_input(record: &RecordEntry) -> bool {
+    let is_button = |k: &AnyKey| matches!(k, AnyKey::Controller(..));
+    record.pressed.iter().any(is_button)
+        || record.released.iter().any(is_button)
+        || record.moves.iter().any(is_controller_offset)
+}
+
+/// The record with only its keyboard and mouse input.
+fn without_controller_input(record: &RecordEntry) -> RecordEntry {
+    let mut res = record.clone();
+    res.pressed.retain(|k| !matches!(k, AnyKey::Controller(..)));
+    res.released
+        .retain(|k| !matches!(k, AnyKey::Controller(..)));
+    res.moves.retain(|m| !is_controller_offset(m));
+    res
+}
+
+fn is_wheel(offset: &AnyOffset) -> bool {
+    matches!(offset, AnyOffset::Wheel(..))
+}
+
+/// The record with its wheel moves split into one move per notch, horizontal first.
+fn per_notch(record: &RecordEntry) -> RecordEntry {
+    let notches = |d: f64| (0..d.abs().round() as usize).map(move |_| d.signum());
+    let mut res = record.clone();
+    res.moves = record
+        .moves
+        .iter()
+        .flat_map(|offset| match *offset {
+            AnyOffset::Wheel(dx, dy) => notches(dx)
+                .map(|x| AnyOffset::Wheel(x, 0.0))
+                .chain(notches(dy).map(|y| AnyOffset::Wheel(0.0, y)))
+                .collect(),
+            _ => vec![offset.clone()],
+        })
+        .collect();
+    res
+}
+
+/// Move the mouse by a delta, for games locking the cursor.
+#[cfg(windows)]
+fn move_relative(dx: i32, dy: i32) -> Result<(), rdev::SimulateError> {
+    use winapi::um::winuser::{SendInput, INPUT, INPUT_MOUSE, MOUSEEVENTF_MOVE, MOUSEINPUT};
+    let mut input = INPUT {
+        type_: INPUT_MOUSE,
+        u: unsafe { std::mem::zeroed() },
+    };
+    unsafe {
+        *input.u.mi_mut() = MOUSEINPUT {
+            dx,
+            dy,
+            mouseData: 0,
+            dwFlags: MOUSEEVENTF_MOVE,
+            time: 0,
+            dwExtraInfo: 0,
+        };
+    }
+    match unsafe { SendInput(1, &mut input, std::mem::size_of::<INPUT>() as i32) } {
+        1 => Ok(()),
+        _ => Err(rdev::SimulateError),
+    }
+}
+
+/// Relative moves need `SendInput`, not available elsewhere.
+#[cfg(not(windows))]
+fn move_relative(_dx: i32, _dy: i32) -> Result<(), rdev::SimulateError> {
+    Err(rdev::SimulateError)
+}
+
+/// Receiver of the virtual controller reports.
+trait PadTarget: std::fmt::Debug + Send {
+    fn update(&mut self, gamepad: &XGamepad) -> Result<(), pad::Error>;
+}
+
+#[cfg(all(windows, feature = "controller"))]
+impl PadTarget for vigem_client::Xbox360Wired<vigem_client::Client> {
+    fn update(&mut self, gamepad: &XGamepad) -> Result<(), pad::Error> {
+        vigem_client::Xbox360Wired::update(self, gamepad)
+    }
+}
+
 #[derive(Debug)]
 struct Controller {
     // client: vigem_client::Client,
-    target: vigem_client::Xbox360Wired<vigem_client::Client>,
-    gamepad: vigem_client::XGamepad,
+    /// `None` until the virtual controller is connected
+    target: Option<Box<dyn PadTarget>>,
+    gamepad: XGamepad,
     updated: bool,
 }
 
 impl Controller {
-    fn new(target: vigem_client::Xbox360Wired<vigem_client::Client>) -> Self {
+    /// Without target, until connected by `attach`.
+    fn detached() -> Self {
         Self {
-            target,
+            target: None,
             gamepad: Default::default(),
-            updated: true,
+            updated: false,
         }
     }
+    /// Send reports to the target, the next update sends the whole state even if neutral.
+    fn attach(&mut self, target: Box<dyn PadTarget>) {
+        self.target = Some(target);
+        self.updated = true;
+    }
 
-    fn try_update(&mut self) {
+    /// Release all buttons and center sticks and triggers, sent by the next update.
+    fn reset(&mut self) {
+        if self.gamepad != XGamepad::default() {
+            self.gamepad = Default::default();
+            self.updated = true;
+        }
+    }
+    /// Release all buttons and center sticks and triggers, the next update sends it
+    /// even if the pad is already neutral. Without target nothing is pending,
+    /// `attach` sends the whole state anyway.
+    fn neutral(&mut self) {
+        self.gamepad = Default::default();
+        self.updated = self.target.is_some();
+    }
+    /// Send all changes since the last successful update in one report.
+    /// The changes stay pending if the update fails, and are sent by the next update.
+    fn try_update(&mut self) -> Result<(), pad::Error> {
         if self.updated {
+            let Some(target) = &mut self.target else {
+                return Err(pad::Error::NotPluggedIn);
+            };
+            target.update(&self.gamepad)?;
             self.updated = false;
-            self.target.update(&self.gamepad).unwrap();
         }
+        Ok(())
     }
 
     fn press(&mut self, btn: u16) {
@@ -260,6 +1207,12 @@ impl Controller {
             self.gamepad.buttons.raw ^= btn;
         }
     }
+    fn left_trigger(&self) -> f64 {
+        self.gamepad.left_trigger as f64 / u8::MAX as f64
+    }
+    fn right_trigger(&self) -> f64 {
+        self.gamepad.right_trigger as f64 / u8::MAX as f64
+    }
     fn trigger(&mut self, l: f64, r: f64) {
         let l = (l * u8::MAX as f64).round() as u8;
         let r = (r * u8::MAX as f64).round() as u8;
@@ -288,3 +1241,869 @@ impl Controller {
         }
     }
 }
+
+/// Accepts every report, a virtual controller touching no device, for dry runs and tests.
+#[derive(Debug)]
+struct NullTarget;
+
+impl PadTarget for NullTarget {
+    fn update(&mut self, _: &XGamepad) -> Result<(), pad::Error> {
+        Ok(())
+    }
+}
+
+/// Keeps the reports with the time they were sent at, shared by its clones.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+struct MockTarget {
+    reports: Arc<RwLock<Vec<(std::time::Instant, XGamepad)>>>,
+    /// fails every update, keeping nothing
+    not_ready: bool,
+}
+
+#[cfg(test)]
+impl PadTarget for MockTarget {
+    fn update(&mut self, gamepad: &XGamepad) -> Result<(), pad::Error> {
+        if self.not_ready {
+            return Err(pad::Error::TargetNotReady);
+        }
+        let now = std::time::Instant::now();
+        self.reports.write().unwrap().push((now, *gamepad));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl MockTarget {
+    /// Connect a clone, for the player thread to send the reports to.
+    fn connect(&self) -> impl Fn() -> Result<Box<dyn PadTarget>, String> + Send + Sync + 'static {
+        let target = self.clone();
+        move || Ok(Box::new(target.clone()))
+    }
+    fn reports(&self) -> Vec<XGamepad> {
+        self.reports.read().unwrap().iter().map(|r| r.1).collect()
+    }
+    /// The reports with their time in ms since `start`.
+    fn reports_since(&self, start: std::time::Instant) -> Vec<(f64, XGamepad)> {
+        let reports = self.reports.read().unwrap();
+        reports
+            .iter()
+            .map(|(t, gamepad)| ((*t - start).as_secs_f64() * 1000.0, *gamepad))
+            .collect()
+    }
+    fn clear(&self) {
+        self.reports.write().unwrap().clear();
+    }
+}
+
+/// Keys sent in one call of a `KeyInjector`.
+#[cfg(test)]
+type KeyCall = Vec<(rdev::Key, bool)>;
+
+/// Keeps the keys of each call instead of sending them, shared by its clones.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+struct MockInjector(Arc<RwLock<Vec<KeyCall>>>);
+
+#[cfg(test)]
+impl KeyInjector for MockInjector {
+    fn send(&mut self, keys: &[(rdev::Key, bool)]) -> usize {
+        self.0.write().unwrap().push(keys.to_vec());
+        keys.len()
+    }
+}
+
+#[cfg(test)]
+impl MockInjector {
+    fn calls(&self) -> Vec<KeyCall> {
+        self.0.read().unwrap().clone()
+    }
+    /// The keys of all the calls in order.
+    fn keys(&self) -> Vec<(rdev::Key, bool)> {
+        self.0.read().unwrap().concat()
+    }
+}
+
+/// Poll `done` every ms until it is true, false if it is not within `timeout_ms`.
+#[cfg(test)]
+pub(crate) fn wait_until(timeout_ms: u64, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(timeout_ms);
+    while !done() {
+        if std::time::Instant::now() > deadline {
+            return false;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    true
+}
+
+#[test]
+fn test_benchmark_playback() {
+    let records: Vec<_> = (0..1000)
+        .map(|i| RecordEntry {
+            ms: i as f64 * 0.1,
+            pressed: vec![AnyKey::Controller(0, 0x1000)],
+            released: vec![AnyKey::Controller(0, 0x2000)],
+            moves: vec![AnyOffset::LeftStick(0, (i % 10) as f64 / 10.0, 0.0)],
+            ..Default::default()
+        })
+        .collect();
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    let res = player.benchmark_playback(&records);
+    assert_eq!(res.events, 3000);
+    assert_eq!(res.failed, 0);
+    assert!(res.events_per_sec() > 0.0);
+    assert!(player.is_done());
+}
+
+#[test]
+fn test_trailing_hold() {
+    let records: Vec<_> = [0.0, 50.0]
+        .into_iter()
+        .map(|ms| RecordEntry {
+            ms,
+            pressed: vec![AnyKey::Controller(0, 0x1000)],
+            ..Default::default()
+        })
+        .collect();
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    player.set_options(PlaybackOptions {
+        trailing_hold_ms: 200.0,
+        ..Default::default()
+    });
+    let start = std::time::Instant::now();
+    player.start_playback(&records);
+    assert!(wait_until(2000, || player.get_progress() == 2));
+    // still playing after the last record, until the hold ends
+    let elapsed = || start.elapsed().as_secs_f64() * 1000.0;
+    assert!(!player.is_done() || elapsed() >= 250.0);
+    assert!(wait_until(2000, || player.is_done()));
+    assert!(elapsed() >= 250.0, "done after {}ms", elapsed());
+
+    // stops right after the last record without trailing hold
+    player.set_options(PlaybackOptions::default());
+    let start = std::time::Instant::now();
+    player.start_playback(&records);
+    assert!(wait_until(2000, || player.is_done()));
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    assert!((50.0..250.0).contains(&elapsed), "done after {elapsed}ms");
+}
+
+#[test]
+fn test_batch_keyboard() {
+    let injector = MockInjector::default();
+    let records = vec![RecordEntry {
+        ms: 0.0,
+        pressed: vec![
+            AnyKey::from(rdev::Key::ShiftLeft),
+            AnyKey::Controller(0, 0x1000),
+            AnyKey::from(rdev::Key::KeyW),
+            AnyKey::from(rdev::Key::KeyD),
+        ],
+        released: vec![AnyKey::from(rdev::Key::KeyA)],
+        ..Default::default()
+    }];
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    player.set_key_injector(Box::new(injector.clone()));
+    player.set_options(PlaybackOptions {
+        batch_keyboard: true,
+        ..Default::default()
+    });
+    let res = player.benchmark_playback(&records);
+    assert_eq!(res.failed, 0);
+    assert_eq!(
+        injector.calls(),
+        vec![vec![
+            (rdev::Key::ShiftLeft, true),
+            (rdev::Key::KeyW, true),
+            (rdev::Key::KeyD, true),
+            (rdev::Key::KeyA, false),
+        ]]
+    );
+}
+
+#[test]
+fn test_controller_update() {
+    let target = MockTarget::default();
+    let mut controller = Controller::detached();
+    controller.attach(Box::new(target.clone()));
+    controller.try_update().unwrap();
+    target.clear();
+
+    let record = RecordEntry {
+        ms: 0.0,
+        pressed: vec![AnyKey::Controller(0, 0x1000), AnyKey::Controller(0, 0x4000)],
+        moves: vec![
+            AnyOffset::Trigger(0, 1.0, 0.0),
+            AnyOffset::LeftStick(0, 1.0, 0.0),
+            AnyOffset::RightStick(0, 0.0, -1.0),
+        ],
+        ..Default::default()
+    };
+    assert_eq!(
+        Player::emit(
+            &record,
+            &mut controller,
+            &mut SendInputBatch,
+            &mut RdevKeys,
+            &Default::default()
+        ),
+        0
+    );
+    let expected = XGamepad {
+        buttons: pad::XButtons { raw: 0x5000 },
+        left_trigger: 255,
+        thumb_lx: i16::MAX,
+        thumb_ry: -i16::MAX,
+        ..Default::default()
+    };
+    assert_eq!(target.reports(), vec![expected]);
+
+    // nothing changed, nothing sent
+    assert_eq!(
+        Player::emit(
+            &record,
+            &mut controller,
+            &mut SendInputBatch,
+            &mut RdevKeys,
+            &Default::default()
+        ),
+        0
+    );
+    assert_eq!(target.reports().len(), 1);
+
+    // a failed update keeps the changes for the next one
+    controller.target = Some(Box::new(MockTarget {
+        not_ready: true,
+        ..target.clone()
+    }));
+    let release = RecordEntry {
+        ms: 1.0,
+        released: vec![AnyKey::Controller(0, 0x1000)],
+        ..Default::default()
+    };
+    assert_eq!(
+        Player::emit(
+            &release,
+            &mut controller,
+            &mut SendInputBatch,
+            &mut RdevKeys,
+            &Default::default()
+        ),
+        1
+    );
+    controller.target = Some(Box::new(target.clone()));
+    let stick = RecordEntry {
+        ms: 2.0,
+        moves: vec![AnyOffset::LeftStick(0, 0.0, 0.0)],
+        ..Default::default()
+    };
+    assert_eq!(
+        Player::emit(
+            &stick,
+            &mut controller,
+            &mut SendInputBatch,
+            &mut RdevKeys,
+            &Default::default()
+        ),
+        0
+    );
+    let expected = XGamepad {
+        buttons: pad::XButtons { raw: 0x4000 },
+        left_trigger: 255,
+        thumb_ry: -i16::MAX,
+        ..Default::default()
+    };
+    assert_eq!(target.reports()[1..], [expected]);
+}
+
+#[test]
+fn test_wait_entry() {
+    let entry = |ms| RecordEntry {
+        ms,
+        pressed: vec![AnyKey::Controller(0, 0x1000)],
+        ..Default::default()
+    };
+    let records = vec![entry(0.0), RecordEntry::wait(10.0, 200.0), entry(20.0)];
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    let start = std::time::Instant::now();
+    player.start_playback(&records);
+    assert!(wait_until(2000, || player.is_done()));
+    assert_eq!(player.get_progress(), 3);
+    // the entry at 20ms is played after the wait
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    assert!(elapsed >= 220.0, "done after {elapsed}ms");
+}
+
+#[test]
+fn test_mouse_steps() {
+    // slow enough
+    assert_eq!(
+        mouse_steps((0.0, 0.0), (30.0, 40.0), 10.0, 5.0),
+        vec![(0.0, 30.0, 40.0)]
+    );
+    let steps = mouse_steps((0.0, 0.0), (1000.0, 0.0), 0.0, 2.0);
+    assert_eq!(steps.len(), 30);
+    assert_eq!(steps.last(), Some(&(MOUSE_STEP_MS, 1000.0, 0.0)));
+    let mut last = (0.0, 0.0);
+    // the jump itself was instantaneous, so each step waits the whole interval
+    for &(delay, x, y) in &steps {
+        assert_eq!(y, 0.0);
+        assert!(x > last.0);
+        assert!((x - last.0) / delay <= 2.0 + 1e-9);
+        last = (x, y);
+    }
+    // the time already elapsed counts for the first step
+    let steps = mouse_steps((0.0, 0.0), (100.0, 0.0), 10.0, 2.0);
+    assert_eq!(steps.len(), 3);
+    assert!((steps[0].0 - (MOUSE_STEP_MS - 10.0)).abs() < 1e-9);
+}
+
+#[test]
+fn test_capped_mouse_move() {
+    let entry = |ms, x| RecordEntry {
+        ms,
+        moves: vec![AnyOffset::Mouse(x, 0.0)],
+        ..Default::default()
+    };
+    // 100px in 10ms at 1px/ms, in 6 steps
+    let records = vec![entry(0.0, 0.0), entry(10.0, 100.0)];
+    let engine = EngineSink::default();
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    player.set_engine_sink(Some(engine.clone()));
+    player.set_options(PlaybackOptions {
+        max_mouse_speed_px_per_ms: Some(1.0),
+        ..Default::default()
+    });
+    let start = std::time::Instant::now();
+    player.start_playback(&records);
+    assert!(wait_until(2000, || player.is_done()));
+    // not faster than the cap
+    let taken = start.elapsed().as_secs_f64() * 1000.0;
+    assert!(taken >= 100.0 - MOUSE_STEP_MS, "moved in {taken}ms");
+    let events: Vec<_> = engine.0.lock().unwrap().drain(..).collect();
+    let xs: Vec<_> = events
+        .iter()
+        .map(|e| match e.1 {
+            RecordedEvent::Move(AnyOffset::Mouse(x, _)) => x,
+            _ => f64::NAN,
+        })
+        .collect();
+    assert_eq!(xs.len(), 7);
+    assert!(xs.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(xs.last(), Some(&100.0));
+    assert!(events.windows(2).all(|w| w[0].0 <= w[1].0));
+
+    // stopped in the middle of the move, the player keeps going through its loop
+    player.start_playback(&records);
+    assert!(wait_until(2000, || engine.0.lock().unwrap().len() >= 3));
+    assert!(player.is_healthy(MOUSE_STEP_MS * 3.0));
+    player.stop_playback();
+    assert!(wait_until(2000, || player.is_done()));
+    let events: Vec<_> = engine.0.lock().unwrap().drain(..).collect();
+    assert!(events.len() < 7);
+    assert!(!events
+        .iter()
+        .any(|e| e.1 == RecordedEvent::Move(AnyOffset::Mouse(100.0, 0.0))));
+}
+
+#[test]
+fn test_playback_capture() {
+    let path = std::env::temp_dir().join("gmc_test_playback_capture.txt");
+    let path = path.to_str().unwrap();
+    let records = vec![
+        RecordEntry {
+            ms: 0.0,
+            pressed: vec![AnyKey::Controller(0, 0x1000)],
+            moves: vec![AnyOffset::LeftStick(0, 0.5, 0.0)],
+            ..Default::default()
+        },
+        RecordEntry {
+            ms: 20.0,
+            released: vec![AnyKey::Controller(0, 0x1000)],
+            ..Default::default()
+        },
+    ];
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    player.set_options(PlaybackOptions {
+        playback_capture_path: Some(path.to_string()),
+        ..Default::default()
+    });
+    player.start_playback(&records);
+    // the capture is flushed on stop, before done
+    assert!(wait_until(2000, || player.is_done()));
+    let capture = std::fs::read_to_string(path).unwrap();
+    let _ = std::fs::remove_file(path);
+    let lines: Vec<_> = capture.lines().collect();
+    let actions: Vec<_> = lines.iter().map(|l| l.split_once(' ').unwrap().1).collect();
+    assert_eq!(
+        actions,
+        vec![
+            "press Controller(0, 4096)",
+            "move LeftStick(0, 0.5, 0.0)",
+            "release Controller(0, 4096)",
+        ]
+    );
+    let ms: Vec<f64> = lines
+        .iter()
+        .map(|l| l.split(' ').next().unwrap().parse().unwrap())
+        .collect();
+    assert!(ms[0] < 20.0 && ms[2] >= 20.0);
+}
+
+#[test]
+fn test_rumble_cues() {
+    type Rumbles = Arc<RwLock<Vec<(f64, u16, u16)>>>;
+    #[derive(Debug)]
+    struct MockRumble(Rumbles, std::time::Instant);
+    impl RumbleSink for MockRumble {
+        fn rumble(&mut self, left: u16, right: u16) {
+            let ms = self.1.elapsed().as_secs_f64() * 1000.0;
+            self.0.write().unwrap().push((ms, left, right));
+        }
+    }
+    let records: Vec<_> = [0.0, 200.0]
+        .into_iter()
+        .map(|ms| RecordEntry {
+            ms,
+            pressed: vec![AnyKey::Controller(0, 0x1000)],
+            ..Default::default()
+        })
+        .collect();
+    let rumbles = Arc::new(RwLock::new(Vec::new()));
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    player.set_options(PlaybackOptions {
+        rumble_cues: vec![(50.0, 1000, 2000, 30)],
+        ..Default::default()
+    });
+    let start = std::time::Instant::now();
+    player.set_rumble_sink(Box::new(MockRumble(rumbles.clone(), start)));
+    player.start_playback(&records);
+    assert!(wait_until(2000, || player.is_done()));
+    let rumbles = rumbles.read().unwrap();
+    assert_eq!(rumbles.len(), 2);
+    // never early, late by the load of the machine
+    let (at, left, right) = rumbles[0];
+    assert_eq!((left, right), (1000, 2000));
+    assert!((50.0..150.0).contains(&at), "rumble at {at}ms");
+    let (stop, left, right) = rumbles[1];
+    assert_eq!((left, right), (0, 0));
+    assert!((80.0..250.0).contains(&stop), "rumble stops at {stop}ms");
+}
+
+#[test]
+fn test_watchdog() {
+    #[derive(Debug)]
+    struct HungTarget;
+    impl PadTarget for HungTarget {
+        fn update(&mut self, _: &XGamepad) -> Result<(), pad::Error> {
+            loop {
+                std::thread::park();
+            }
+        }
+    }
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(HungTarget))).unwrap();
+    assert!(player.is_healthy(50.0));
+    assert!(!player.watchdog(50.0));
+    let records = vec![RecordEntry {
+        ms: 0.0,
+        pressed: vec![AnyKey::Controller(0, 0x1000)],
+        ..Default::default()
+    }];
+    player.start_playback(&records);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert!(!player.is_done());
+    assert!(!player.is_healthy(50.0));
+    assert!(player.watchdog(50.0));
+    assert_eq!(player.restarts, 1);
+    // a fresh player, stopped
+    assert!(player.is_done());
+    assert!(player.is_healthy(50.0));
+    assert_eq!(player.get_progress(), 0);
+    player.set_progress(0);
+}
+
+#[test]
+fn test_key_probability() {
+    let key = AnyKey::from(rdev::Key::KeyA);
+    let entry = |pressed: &[AnyKey], released: &[AnyKey]| RecordEntry {
+        ms: 0.0,
+        pressed: pressed.to_vec(),
+        released: released.to_vec(),
+        ..Default::default()
+    };
+    let other = AnyKey::from(rdev::Key::KeyB);
+    let press = entry(&[key.clone(), other.clone()], &[]);
+    let release = entry(&[], &[key.clone(), other.clone()]);
+    let rules = vec![(key.clone(), 0.5)];
+    let mut rng = Rng(42);
+    let mut skipped = Vec::new();
+    let mut fired = 0;
+    for _ in 0..1000 {
+        let pressed = vary(&press, &rules, &mut rng, &mut skipped);
+        let released = vary(&release, &rules, &mut rng, &mut skipped);
+        // keys without a rule always play
+        assert!(pressed.pressed.contains(&other));
+        assert!(released.released.contains(&other));
+        // a skipped press skips its release
+        assert_eq!(
+            pressed.pressed.contains(&key),
+            released.released.contains(&key)
+        );
+        fired += pressed.pressed.contains(&key) as usize;
+    }
+    assert!(skipped.is_empty());
+    assert!((450..=550).contains(&fired), "fired {fired} of 1000");
+    // the same seed gives the same variation
+    let mut again = Rng(42);
+    let mut skipped = Vec::new();
+    let first = vary(&press, &rules, &mut again, &mut skipped);
+    let mut rng = Rng(42);
+    assert_eq!(first, vary(&press, &rules, &mut rng, &mut Vec::new()));
+}
+
+#[test]
+fn test_split_triggers() {
+    let mut controller = Controller::detached();
+    controller.attach(Box::new(NullTarget));
+    let entry = |moves| RecordEntry {
+        ms: 0.0,
+        moves,
+        ..Default::default()
+    };
+    Player::emit(
+        &entry(vec![AnyOffset::Trigger(0, 0.0, 1.0)]),
+        &mut controller,
+        &mut SendInputBatch,
+        &mut RdevKeys,
+        &Default::default(),
+    );
+    Player::emit(
+        &entry(vec![AnyOffset::LeftTrigger(0, 0.2)]),
+        &mut controller,
+        &mut SendInputBatch,
+        &mut RdevKeys,
+        &Default::default(),
+    );
+    assert_eq!(
+        (
+            controller.gamepad.left_trigger,
+            controller.gamepad.right_trigger
+        ),
+        (51, 255)
+    );
+    Player::emit(
+        &entry(vec![AnyOffset::RightTrigger(0, 0.0)]),
+        &mut controller,
+        &mut SendInputBatch,
+        &mut RdevKeys,
+        &Default::default(),
+    );
+    assert_eq!(
+        (
+            controller.gamepad.left_trigger,
+            controller.gamepad.right_trigger
+        ),
+        (51, 0)
+    );
+}
+
+#[test]
+fn test_loop_playback() {
+    let (a, b) = (rdev::Key::KeyA, rdev::Key::KeyB);
+    let entry = |ms, pressed: &[rdev::Key], released: &[rdev::Key]| RecordEntry {
+        ms,
+        pressed: pressed.iter().map(|&k| AnyKey::from(k)).collect(),
+        released: released.iter().map(|&k| AnyKey::from(k)).collect(),
+        ..Default::default()
+    };
+    // A is held over the end of the loop, B is held at the end but released on looping
+    let records = vec![
+        entry(0.0, &[a], &[]),
+        entry(20.0, &[b], &[]),
+        entry(40.0, &[], &[]),
+    ];
+    let injector = MockInjector::default();
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    player.set_key_injector(Box::new(injector.clone()));
+    player.set_options(PlaybackOptions {
+        batch_keyboard: true,
+        ..Default::default()
+    });
+    player.set_loop(true);
+    player.start_playback(&records);
+    // B is pressed again each time the playback wraps back to the start, looped twice
+    let b_presses = || injector.keys().iter().filter(|c| **c == (b, true)).count();
+    assert!(wait_until(1000, || b_presses() >= 3));
+    assert!(!player.is_done());
+    player.stop_playback();
+    assert!(wait_until(1000, || player.is_done()));
+
+    let calls = injector.keys();
+    // A is pressed only once and released on stop, B is released before each press again
+    let a_calls: Vec<_> = calls.iter().filter(|c| c.0 == a).map(|c| c.1).collect();
+    assert_eq!(a_calls, vec![true, false]);
+    let b_calls: Vec<_> = calls.iter().filter(|c| c.0 == b).map(|c| c.1).collect();
+    assert!(b_calls.windows(2).all(|w| w[0] != w[1]));
+}
+
+#[test]
+fn test_release_on_stop() {
+    let (target, injector) = (MockTarget::default(), MockInjector::default());
+    let records = vec![
+        RecordEntry {
+            ms: 0.0,
+            pressed: vec![
+                AnyKey::from(rdev::Key::ShiftLeft),
+                AnyKey::Controller(0, 0x1000),
+            ],
+            moves: vec![
+                AnyOffset::Trigger(0, 1.0, 0.5),
+                AnyOffset::LeftStick(0, 1.0, 0.0),
+            ],
+            ..Default::default()
+        },
+        RecordEntry {
+            ms: 1000.0,
+            released: vec![AnyKey::from(rdev::Key::ShiftLeft)],
+            ..Default::default()
+        },
+    ];
+    let mut player = RecordPlayer::new();
+    player.spawn(target.connect()).unwrap();
+    player.set_key_injector(Box::new(injector.clone()));
+    player.set_options(PlaybackOptions {
+        batch_keyboard: true,
+        ..Default::default()
+    });
+    player.start_playback(&records);
+    assert!(wait_until(500, || player.get_progress() == 1));
+    player.stop_playback();
+    assert!(wait_until(500, || player.is_done()));
+    assert_eq!(
+        injector.keys(),
+        vec![(rdev::Key::ShiftLeft, true), (rdev::Key::ShiftLeft, false)]
+    );
+    let reports = target.reports();
+    assert_ne!(reports[reports.len() - 2], Default::default());
+    assert_eq!(reports.last(), Some(&Default::default()));
+}
+
+#[test]
+fn test_no_controller() {
+    let injector = MockInjector::default();
+    let records = vec![
+        RecordEntry {
+            ms: 0.0,
+            pressed: vec![AnyKey::from(rdev::Key::KeyW), AnyKey::Controller(0, 0x1000)],
+            moves: vec![AnyOffset::LeftStick(0, 1.0, 0.0)],
+            ..Default::default()
+        },
+        RecordEntry {
+            ms: 20.0,
+            released: vec![AnyKey::from(rdev::Key::KeyW), AnyKey::Controller(0, 0x1000)],
+            ..Default::default()
+        },
+    ];
+    let mut player = RecordPlayer::new();
+    player
+        .spawn(|| Err("ViGEmBus not installed".to_string()))
+        .unwrap();
+    player.set_key_injector(Box::new(injector.clone()));
+    player.set_options(PlaybackOptions {
+        batch_keyboard: true,
+        ..Default::default()
+    });
+    player.start_playback(&records);
+    assert!(wait_until(1000, || player.is_done()));
+    assert!(player.is_healthy(1000.0));
+    assert_eq!(
+        injector.keys(),
+        vec![(rdev::Key::KeyW, true), (rdev::Key::KeyW, false)]
+    );
+    // nothing is left pending on the missing pad after the playback
+    let keyboard_only = [without_controller_input(&records[0])];
+    assert_eq!(player.benchmark_playback(&keyboard_only).failed, 0);
+}
+
+#[test]
+fn test_neutral_flush() {
+    let target = MockTarget::default();
+    let entry = |ms, btn| RecordEntry {
+        ms,
+        pressed: vec![AnyKey::Controller(0, btn)],
+        moves: vec![AnyOffset::LeftStick(0, 1.0, 0.0)],
+        ..Default::default()
+    };
+    let mut player = RecordPlayer::new();
+    player.spawn(target.connect()).unwrap();
+    // the benchmark leaves the button pressed and the stick moved
+    let res = player.benchmark_playback(&[entry(0.0, 0x1000)]);
+    assert_eq!(res.failed, 0);
+    let last = *target.reports().last().unwrap();
+    assert_eq!(last.buttons.raw, 0x1000);
+    target.clear();
+
+    player.start_playback(&[entry(50.0, 0x2000)]);
+    assert!(wait_until(1000, || target.reports().len() >= 2));
+    let reports = target.reports();
+    // the neutral state is sent before the first record
+    assert_eq!(reports[0], XGamepad::default());
+    assert_eq!(reports[1].buttons.raw, 0x2000);
+    assert_eq!(reports[1].thumb_lx, i16::MAX);
+}
+
+#[test]
+fn test_max_button_hold() {
+    let target = MockTarget::default();
+    let start = std::time::Instant::now();
+    let records = vec![
+        RecordEntry {
+            ms: 0.0,
+            pressed: vec![AnyKey::Controller(0, 0x1000), AnyKey::Controller(0, 0x2000)],
+            ..Default::default()
+        },
+        RecordEntry {
+            ms: 20.0,
+            released: vec![AnyKey::Controller(0, 0x2000)],
+            ..Default::default()
+        },
+    ];
+    let mut player = RecordPlayer::new();
+    player.spawn(target.connect()).unwrap();
+    player.set_options(PlaybackOptions {
+        trailing_hold_ms: 200.0,
+        max_button_hold_ms: Some(50.0),
+        ..Default::default()
+    });
+    player.start_playback(&records);
+    // the unreleased button is released while still in the trailing hold
+    assert!(wait_until(1000, || target.reports().len() >= 3));
+    assert!(!player.is_done());
+    let reports = target.reports_since(start);
+    let buttons: Vec<_> = reports.iter().map(|(_, g)| g.buttons.raw).collect();
+    assert_eq!(buttons, vec![0x3000, 0x1000, 0]);
+    let held = reports[2].0 - reports[0].0;
+    assert!((50.0..150.0).contains(&held), "released after {held}ms");
+}
+
+#[test]
+fn test_engine_sink() {
+    let records = vec![
+        RecordEntry {
+            ms: 0.0,
+            pressed: vec![AnyKey::from(rdev::Key::KeyW), AnyKey::Controller(0, 0x1000)],
+            moves: vec![AnyOffset::Mouse(10.0, 20.0)],
+            ..Default::default()
+        },
+        RecordEntry {
+            ms: 60.0,
+            released: vec![AnyKey::from(rdev::Key::KeyW)],
+            moves: vec![AnyOffset::LeftStick(0, 1.0, 0.0)],
+            ..Default::default()
+        },
+    ];
+    let engine = EngineSink::default();
+    let mut player = RecordPlayer::new();
+    // the virtual controller is not needed
+    player
+        .spawn(|| Err("ViGEmBus not installed".to_string()))
+        .unwrap();
+    player.set_engine_sink(Some(engine.clone()));
+    player.start_playback(&records);
+    assert!(wait_until(1000, || player.is_done()));
+    let events: Vec<_> = engine.0.lock().unwrap().drain(..).collect();
+    let actions: Vec<_> = events.iter().map(|(_, e)| e.clone()).collect();
+    assert_eq!(
+        actions,
+        vec![
+            RecordedEvent::Press(AnyKey::from(rdev::Key::KeyW)),
+            RecordedEvent::Press(AnyKey::Controller(0, 0x1000)),
+            RecordedEvent::Move(AnyOffset::Mouse(10.0, 20.0)),
+            RecordedEvent::Release(AnyKey::from(rdev::Key::KeyW)),
+            RecordedEvent::Move(AnyOffset::LeftStick(0, 1.0, 0.0)),
+            // the button held at the end is released on stop
+            RecordedEvent::Release(AnyKey::Controller(0, 0x1000)),
+        ]
+    );
+    assert!(events[..3].iter().all(|e| e.0 < 20.0));
+    assert!(events[3..5].iter().all(|e| (60.0..80.0).contains(&e.0)));
+    assert!(events.windows(2).all(|w| w[0].0 <= w[1].0));
+}
+
+#[test]
+fn test_wheel_notches() {
+    let records = vec![RecordEntry {
+        ms: 0.0,
+        moves: vec![AnyOffset::Wheel(-1.0, 2.0), AnyOffset::Mouse(1.0, 2.0)],
+        ..Default::default()
+    }];
+    let engine = EngineSink::default();
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    player.set_engine_sink(Some(engine.clone()));
+    player.start_playback(&records);
+    assert!(wait_until(1000, || player.is_done()));
+    let moves: Vec<_> = engine.0.lock().unwrap().drain(..).map(|e| e.1).collect();
+    assert_eq!(
+        moves,
+        vec![
+            RecordedEvent::Move(AnyOffset::Wheel(-1.0, 0.0)),
+            RecordedEvent::Move(AnyOffset::Wheel(0.0, 1.0)),
+            RecordedEvent::Move(AnyOffset::Wheel(0.0, 1.0)),
+            RecordedEvent::Move(AnyOffset::Mouse(1.0, 2.0)),
+        ]
+    );
+}
+
+#[test]
+fn test_key_backends() {
+    let (send_input, rdev_keys) = (MockInjector::default(), MockInjector::default());
+    let (w, f12) = (rdev::Key::KeyW, rdev::Key::F12);
+    let records = vec![RecordEntry {
+        ms: 0.0,
+        pressed: vec![AnyKey::from(w), AnyKey::from(f12)],
+        released: vec![AnyKey::from(f12), AnyKey::from(w)],
+        ..Default::default()
+    }];
+    let mut player = RecordPlayer::new();
+    player.spawn(|| Ok(Box::new(NullTarget))).unwrap();
+    player.set_key_injector(Box::new(send_input.clone()));
+    player.set_rdev_injector(Box::new(rdev_keys.clone()));
+    // only the overridden key goes to the key injector, W is simulated by rdev
+    player.set_options(PlaybackOptions {
+        key_backends: vec![(AnyKey::from(f12), KeyBackend::SendInput)],
+        ..Default::default()
+    });
+    assert_eq!(player.benchmark_playback(&records).failed, 0);
+    assert_eq!(send_input.keys(), vec![(f12, true), (f12, false)]);
+    assert_eq!(rdev_keys.keys(), vec![(w, true), (w, false)]);
+
+    // and the other way around when batching the keyboard, in the order of the entry
+    let both = MockInjector::default();
+    player.set_key_injector(Box::new(both.clone()));
+    player.set_rdev_injector(Box::new(both.clone()));
+    player.set_options(PlaybackOptions {
+        batch_keyboard: true,
+        key_backends: vec![(AnyKey::from(f12), KeyBackend::Rdev)],
+        ..Default::default()
+    });
+    assert_eq!(player.benchmark_playback(&records).failed, 0);
+    assert_eq!(
+        both.calls(),
+        vec![
+            vec![(w, true)],
+            vec![(f12, true), (f12, false)],
+            vec![(w, false)]
+        ]
+    );
+}
@@ -1,10 +1,24 @@
-use crate::player::RecordPlayer;
+use crate::foreground::{ForegroundSource, SystemForeground};
+use crate::keyboard::KeyBackend;
+use crate::player::{self, EngineSink, PlaybackOptions, RecordPlayer};
 use crate::state::{
-    AnyKey, AnyOffset, ControllerEvent, ControllerRaw, GlobalState, ShortCut, ShortCuts,
+    circle_to_square, AllOffsets, AnyKey, AnyOffset, ControllerEvent, GlobalState,
+    MouseToControllerMap, ShortCut, ShortCuts,
 };
+use crate::trigger::{MidiTriggerConfig, TriggerSignal, TriggerSource};
 use log::{debug, info, warn};
-use std::{sync::mpsc::Receiver, thread::JoinHandle};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    thread::JoinHandle,
+};
 
+#[cfg(all(windows, feature = "controller"))]
+use crate::{
+    player::RumbleSink,
+    state::{poll_controllers, ControllerRaw, PadSource, PadState},
+};
+#[cfg(all(windows, feature = "controller"))]
 use rusty_xinput::XInputHandle;
 use serde::{Deserialize, Serialize};
 
@@ -14,8 +28,88 @@ pub struct Config {
     pub enable_mouse: bool,
     pub enable_keyboard: bool,
     pub enable_controller: [bool; 4],
+    /// Poll controllers on a high resolution timer, so that short intervals are honored
+    /// at the cost of a busier CPU.
+    #[serde(default)]
+    pub high_res_timer: bool,
 
     pub screen_scale: f64,
+    /// Scale of recorded stick magnitude, clamped to 1.0.
+    #[serde(default = "Config::default_stick_sensitivity")]
+    pub stick_sensitivity: f64,
+    /// Record sticks in circular space, and map them back to square space on playback.
+    #[serde(default)]
+    pub circular_normalize: bool,
+    /// Stick magnitude a stick direction of shortcuts has to exceed, against drift.
+    #[serde(default = "Config::default_stick_deadzone")]
+    pub stick_deadzone: f64,
+    /// Record each trigger on its own when only one changes, instead of both together.
+    #[serde(default)]
+    pub split_triggers: bool,
+    /// Record every event in its own entry instead of grouping events within 1ms.
+    #[serde(default)]
+    pub no_coalesce: bool,
+    /// Record mouse movement as deltas for a locked cursor, replayed as relative moves.
+    #[serde(default)]
+    pub camera_mode: bool,
+    /// Raw wheel delta of one notch, wheel deltas are recorded as whole notches.
+    #[serde(default = "Config::default_wheel_notch_size")]
+    pub wheel_notch_size: f64,
+    /// Inactivity after which recording pauses until the next input,
+    /// the idle time is collapsed to `IDLE_GAP_MS`.
+    pub auto_pause_idle_ms: Option<f64>,
+    /// Pause inserted between the last record and the appended ones.
+    #[serde(default)]
+    pub append_gap_ms: f64,
+    /// Step of seek_forward and seek_back while playing.
+    #[serde(default = "Config::default_seek_step_ms")]
+    pub seek_step_ms: f64,
+
+    /// Time to keep the state after the last record before stopping playback.
+    #[serde(default)]
+    pub trailing_hold_ms: f64,
+    /// Repeat the playback from the start until stopped, toggled by `toggle_loop`.
+    #[serde(default)]
+    pub loop_playback: bool,
+    /// Restart the player if it makes no progress for this long while playing, 0 to disable.
+    #[serde(default = "Config::default_player_watchdog_ms")]
+    pub player_watchdog_ms: f64,
+    /// Replay the keyboard events of a record in one batch of hardware scan codes.
+    #[serde(default)]
+    pub batch_keyboard: bool,
+    /// Keys replayed by another backend than the others.
+    #[serde(default)]
+    pub key_backends: Vec<(AnyKey, KeyBackend)>,
+    /// Cap of the replayed mouse speed, faster moves are spread over time.
+    pub max_mouse_speed_px_per_ms: Option<f64>,
+    /// Release controller buttons held longer than this on playback.
+    pub max_button_hold_ms: Option<f64>,
+    /// File to write the played actions to, with the time they are played at.
+    pub playback_capture_path: Option<String>,
+    /// Skip Windows key taps on playback, keeping Windows key combos.
+    #[serde(default)]
+    pub suppress_windows_key: bool,
+    /// Play back mirrored left to right, see `mirror_horizontal`.
+    #[serde(default)]
+    pub mirror_horizontal: bool,
+    /// Pairs of keys swapped by `mirror_horizontal`.
+    #[serde(default = "Config::default_mirror_keys")]
+    pub mirror_keys: Vec<(AnyKey, AnyKey)>,
+    /// Keys pressed on playback only with the probability, skipping their release too.
+    #[serde(default)]
+    pub key_probability: Vec<(AnyKey, f64)>,
+    /// Seed of `key_probability`, a new one each playback if not set.
+    pub random_seed: Option<u64>,
+    /// Replay mouse input on the virtual controller.
+    pub mouse_to_controller: Option<MouseToControllerMap>,
+    /// Start, stop and save by a MIDI device, needs the `midi` feature.
+    pub midi_trigger: Option<MidiTriggerConfig>,
+    /// Process name to config file, loaded when the process comes to foreground.
+    #[serde(default)]
+    pub game_configs: HashMap<String, String>,
+    /// Number of clips kept, saving a new one evicts the least recently used.
+    #[serde(default)]
+    pub max_clips: Option<usize>,
 
     pub start_record: ShortCuts,
     pub append_record: ShortCuts,
@@ -26,6 +120,42 @@ pub struct Config {
     pub drop_record: ShortCuts,
 
     pub save_records: ShortCuts,
+    /// save the records to a new timestamped file and start a new recording, when ready
+    #[serde(default)]
+    pub save_and_new: ShortCuts,
+    /// Directory of the files of `save_and_new`, in `GMC_CONFIG_DIR` if relative or not set.
+    #[serde(default)]
+    pub takes_dir: Option<String>,
+
+    /// Confirm the first playback after launch by pressing the start playback shortcut twice.
+    #[serde(default)]
+    pub require_playback_confirmation: bool,
+    /// Save records as columns of each field, which compress better and are easier to scan.
+    #[serde(default)]
+    pub columnar: bool,
+    /// Keep the records in this file in a compact binary format, instead of in the yaml.
+    /// A relative path is in `GMC_CONFIG_DIR` if set.
+    pub records_file: Option<String>,
+    /// Time the pressed keys have to stay unchanged before a shortcut fires.
+    #[serde(default)]
+    pub shortcut_stable_ms: f64,
+    /// Tell left and right Ctrl, Shift and Alt apart for the side specific modifiers of shortcuts.
+    #[serde(default)]
+    pub distinguish_lr_modifiers: bool,
+    /// Load the pressed keys of the init state as saved, without removing duplicates.
+    #[serde(default)]
+    pub raw_init_state: bool,
+
+    #[serde(default)]
+    pub seek_forward: ShortCuts,
+    #[serde(default)]
+    pub seek_back: ShortCuts,
+    /// toggle looping while playing
+    #[serde(default)]
+    pub toggle_loop: ShortCuts,
+    /// bookmark name to the shortcut jumping to it while playing
+    #[serde(default)]
+    pub jump_to_bookmark: BTreeMap<String, ShortCuts>,
 }
 
 impl Default for Config {
@@ -35,8 +165,37 @@ impl Default for Config {
             enable_mouse: true,
             enable_keyboard: true,
             enable_controller: [true, false, false, false],
+            high_res_timer: false,
 
             screen_scale: 1.0,
+            stick_sensitivity: Self::default_stick_sensitivity(),
+            circular_normalize: false,
+            stick_deadzone: Self::default_stick_deadzone(),
+            split_triggers: false,
+            no_coalesce: false,
+            camera_mode: false,
+            wheel_notch_size: Self::default_wheel_notch_size(),
+            auto_pause_idle_ms: None,
+            append_gap_ms: 0.0,
+            seek_step_ms: Self::default_seek_step_ms(),
+
+            trailing_hold_ms: 0.0,
+            loop_playback: false,
+            player_watchdog_ms: Self::default_player_watchdog_ms(),
+            batch_keyboard: false,
+            key_backends: Vec::new(),
+            max_mouse_speed_px_per_ms: None,
+            max_button_hold_ms: None,
+            playback_capture_path: None,
+            suppress_windows_key: false,
+            mirror_horizontal: false,
+            mirror_keys: Self::default_mirror_keys(),
+            key_probability: Vec::new(),
+            random_seed: None,
+            mouse_to_controller: None,
+            midi_trigger: None,
+            game_configs: HashMap::new(),
+            max_clips: None,
 
             start_record: ShortCuts::Contains(vec![]),
             append_record: ShortCuts::Contains(vec![]),
@@ -46,6 +205,18 @@ impl Default for Config {
             continue_record: ShortCuts::Contains(vec![]),
             drop_record: ShortCuts::Contains(vec![]),
             save_records: ShortCuts::Contains(vec![]),
+            save_and_new: ShortCuts::Contains(vec![]),
+            require_playback_confirmation: false,
+            columnar: false,
+            records_file: None,
+            takes_dir: None,
+            shortcut_stable_ms: 0.0,
+            distinguish_lr_modifiers: false,
+            raw_init_state: false,
+            seek_forward: ShortCuts::Contains(vec![]),
+            seek_back: ShortCuts::Contains(vec![]),
+            toggle_loop: ShortCuts::Contains(vec![]),
+            jump_to_bookmark: BTreeMap::new(),
         }
     }
 }
@@ -76,16 +247,282 @@ impl Config {
             ]),
             // continue_record: ShortCuts::Exclude(vec![ShortCut::EMPTY, ShortCut::ESCAPE]),
             save_records: ShortCuts::Contains(vec![ShortCut::CTRL_RIGHT_S]),
+            seek_forward: ShortCuts::Contains(vec![ShortCut::alt(rdev::Key::RightArrow)]),
+            seek_back: ShortCuts::Contains(vec![ShortCut::alt(rdev::Key::LeftArrow)]),
+            toggle_loop: ShortCuts::Contains(vec![ShortCut::alt(rdev::Key::KeyL)]),
             ..Default::default()
         }
     }
+    fn default_seek_step_ms() -> f64 {
+        1000.0
+    }
+    fn default_player_watchdog_ms() -> f64 {
+        5000.0
+    }
+    fn default_stick_sensitivity() -> f64 {
+        1.0
+    }
+    fn default_stick_deadzone() -> f64 {
+        0.5
+    }
+    fn default_wheel_notch_size() -> f64 {
+        1.0
+    }
+    /// arrows, `A` and `D`, and the D-pad of the first controller
+    fn default_mirror_keys() -> Vec<(AnyKey, AnyKey)> {
+        vec![
+            (rdev::Key::LeftArrow.into(), rdev::Key::RightArrow.into()),
+            (rdev::Key::KeyA.into(), rdev::Key::KeyD.into()),
+            (AnyKey::Controller(0, 0x0004), AnyKey::Controller(0, 0x0008)),
+        ]
+    }
+
+    /// Sleep between controller polls, at least `MIN_POLL_INTERVAL_MS`
+    /// since polling without sleeping keeps a core busy.
+    #[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+    pub fn poll_interval(&self) -> std::time::Duration {
+        if self.interval.is_nan() || self.interval < MIN_POLL_INTERVAL_MS {
+            warn!(
+                "Controller interval {}ms raised to {MIN_POLL_INTERVAL_MS}ms.",
+                self.interval
+            );
+            return std::time::Duration::from_secs_f64(MIN_POLL_INTERVAL_MS / 1000.0);
+        }
+        std::time::Duration::from_secs_f64(self.interval / 1000.0)
+    }
+
+    /// The config file registered for the process, names are case insensitive.
+    pub fn game_config(&self, process: &str) -> Option<&String> {
+        self.game_configs
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(process))
+            .map(|(_, path)| path)
+    }
+}
+
+#[allow(unused)]
+/// this is implement of sharing the config without records
+impl Config {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let s = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_yml::from_str(&s).map_err(|e| e.to_string())
+    }
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        warn!("Save config to file {path}!");
+        let s = serde_yml::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, s).map_err(|e| e.to_string())
+    }
+    /// Differences from other, the shortcuts of each action one by one, then the other
+    /// settings in the order of the fields.
+    pub fn diff(&self, other: &Config) -> Vec<ConfigDiff> {
+        let mut res = Vec::new();
+        let (our_shortcuts, their_shortcuts) = (self.shortcuts(), other.shortcuts());
+        let mut actions: Vec<&String> = our_shortcuts.iter().map(|(action, _)| action).collect();
+        for (action, _) in &their_shortcuts {
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        }
+        fn find<'a>(list: &[(String, &'a ShortCuts)], action: &str) -> Option<&'a ShortCuts> {
+            list.iter().find(|(a, _)| a == action).map(|(_, s)| *s)
+        }
+        let empty = ShortCuts::default();
+        for action in actions {
+            let ours = find(&our_shortcuts, action).unwrap_or(&empty);
+            let theirs = find(&their_shortcuts, action).unwrap_or(&empty);
+            let (ShortCuts::Contains(a) | ShortCuts::Exclude(a)) = ours;
+            let (ShortCuts::Contains(b) | ShortCuts::Exclude(b)) = theirs;
+            if std::mem::discriminant(ours) != std::mem::discriminant(theirs) {
+                res.push(ConfigDiff::Inverted {
+                    action: action.clone(),
+                });
+            }
+            for shortcut in a.iter().filter(|s| !b.contains(s)) {
+                res.push(ConfigDiff::Removed {
+                    action: action.clone(),
+                    shortcut: format!("{shortcut:?}"),
+                });
+            }
+            for shortcut in b.iter().filter(|s| !a.contains(s)) {
+                res.push(ConfigDiff::Added {
+                    action: action.clone(),
+                    shortcut: format!("{shortcut:?}"),
+                });
+            }
+        }
+        let (Ok(serde_yml::Value::Mapping(ours)), Ok(serde_yml::Value::Mapping(theirs))) =
+            (serde_yml::to_value(self), serde_yml::to_value(other))
+        else {
+            return res;
+        };
+        let yaml = |v: Option<&serde_yml::Value>| match v {
+            Some(v) => {
+                serde_yml::to_string(v).map_or_else(|e| e.to_string(), |s| s.trim_end().to_string())
+            }
+            None => String::new(),
+        };
+        for (name, value) in &ours {
+            let Some(name) = name.as_str() else {
+                continue;
+            };
+            if name == "jump_to_bookmark" || our_shortcuts.iter().any(|(a, _)| a == name) {
+                continue;
+            }
+            if theirs.get(name) != Some(value) {
+                res.push(ConfigDiff::Changed {
+                    name: name.to_string(),
+                    ours: yaml(Some(value)),
+                    theirs: yaml(theirs.get(name)),
+                });
+            }
+        }
+        res
+    }
+    /// The shortcuts of each action, the bookmarks sorted by name.
+    fn shortcuts(&self) -> Vec<(String, &ShortCuts)> {
+        let mut res: Vec<(String, &ShortCuts)> = [
+            ("start_record", &self.start_record),
+            ("append_record", &self.append_record),
+            ("stop_record", &self.stop_record),
+            ("start_playback", &self.start_playback),
+            ("stop_playback", &self.stop_playback),
+            ("continue_record", &self.continue_record),
+            ("drop_record", &self.drop_record),
+            ("save_records", &self.save_records),
+            ("save_and_new", &self.save_and_new),
+            ("seek_forward", &self.seek_forward),
+            ("seek_back", &self.seek_back),
+            ("toggle_loop", &self.toggle_loop),
+        ]
+        .into_iter()
+        .map(|(action, shortcuts)| (action.to_string(), shortcuts))
+        .collect();
+        res.extend(
+            self.jump_to_bookmark
+                .iter()
+                .map(|(name, shortcuts)| (format!("jump_to_bookmark.{name}"), shortcuts)),
+        );
+        res
+    }
+}
+
+/// A difference of two configs, see `Config::diff`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum ConfigDiff {
+    /// The shortcut of the action is only in the other config.
+    Added { action: String, shortcut: String },
+    /// The shortcut of the action is only in this config.
+    Removed { action: String, shortcut: String },
+    /// The shortcuts of the action switched between `Contains` and `Exclude`.
+    Inverted { action: String },
+    /// Another setting differs, the values as yaml.
+    Changed {
+        name: String,
+        ours: String,
+        theirs: String,
+    },
+}
+
+/// Shortest sleep between controller polls.
+#[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+const MIN_POLL_INTERVAL_MS: f64 = 1.0;
+
+/// Time before a poll that the high resolution timer spins instead of sleeping.
+#[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+const SPIN_MARGIN: std::time::Duration = std::time::Duration::from_millis(2);
+
+/// Paces the controller polls at a fixed rate.
+///
+/// The plain timer just sleeps, which is as coarse as the system timer (about 15ms
+/// on Windows by default). The high resolution one raises the system timer resolution
+/// and spins the last `SPIN_MARGIN` before each poll.
+#[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+struct PollTimer {
+    interval: std::time::Duration,
+    high_res: bool,
+    next: std::time::Instant,
+}
+
+#[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+impl PollTimer {
+    fn new(interval: std::time::Duration, high_res: bool) -> Self {
+        #[cfg(windows)]
+        if high_res {
+            unsafe { winapi::um::timeapi::timeBeginPeriod(1) };
+        }
+        Self {
+            interval,
+            high_res,
+            next: std::time::Instant::now() + interval,
+        }
+    }
+    /// Wait until the next poll.
+    fn wait(&mut self) {
+        if !self.high_res {
+            std::thread::sleep(self.interval);
+            return;
+        }
+        let now = std::time::Instant::now();
+        if let Some(left) = self.next.checked_duration_since(now) {
+            if left > SPIN_MARGIN {
+                std::thread::sleep(left - SPIN_MARGIN);
+            }
+            while std::time::Instant::now() < self.next {
+                std::hint::spin_loop();
+            }
+            self.next += self.interval;
+        } else {
+            // fell behind, do not try to catch up
+            self.next = now + self.interval;
+        }
+    }
 }
 
+impl Drop for PollTimer {
+    fn drop(&mut self) {
+        #[cfg(windows)]
+        if self.high_res {
+            unsafe { winapi::um::timeapi::timeEndPeriod(1) };
+        }
+    }
+}
+
+/// Time to press the start playback shortcut again to confirm the first playback.
+const PLAYBACK_CONFIRM_MS: f64 = 3000.0;
+
+/// Gap kept in the recording in place of an idle time skipped by `auto_pause_idle_ms`.
+const IDLE_GAP_MS: f64 = 100.0;
+
 enum CallbackType {
     /// Mouse or Keyboard
     MK(f64, rdev::EventType, String),
     /// Controller
+    #[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
     Ctrl(f64, u32, ControllerEvent),
+    /// The keyboard and mouse listener could not start, and the reason
+    ListenerFailed(String),
+}
+
+/// An event as written by `stream_json`, one JSON line each.
+#[derive(Serialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+enum StreamEvent<'a> {
+    /// Mouse or Keyboard
+    Input { ms: f64, event: &'a rdev::EventType },
+    Controller {
+        ms: f64,
+        id: u32,
+        event: &'a ControllerEvent,
+    },
+}
+
+/// Output of the JSON lines of `stream_json`.
+struct JsonStream(Box<dyn std::io::Write + Send>);
+
+impl std::fmt::Debug for JsonStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JsonStream")
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
@@ -100,8 +537,25 @@ pub enum RecorderState {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Recorder {
     config: Config,
-    // init_state: GlobalState,
+    /// pressed keys and offsets when the records started, deduplicated on load
+    #[serde(default)]
+    init_state: GlobalState,
+    /// entries, or columns of them when saved with `columnar`
+    #[serde(deserialize_with = "deserialize_records")]
     records: Vec<RecordEntry>,
+    /// bookmark name to ms in records
+    #[serde(default)]
+    bookmarks: BTreeMap<String, f64>,
+    /// (ms, left motor, right motor, duration ms) rumbles of the listened controllers
+    /// played at the time in records, as feedback while playing
+    #[serde(default)]
+    rumble_cues: Vec<(f64, u16, u16, u64)>,
+    /// saved clips, the least recently used first
+    #[serde(default)]
+    clips: Vec<Clip>,
+    #[serde(skip)]
+    /// names of the clips skipped by `from_file`
+    corrupt_clips: Vec<String>,
 
     #[serde(skip)]
     player: RecordPlayer,
@@ -114,11 +568,55 @@ pub struct Recorder {
     #[serde(skip)]
     rec_should_update: bool,
     #[serde(skip)]
+    /// raw wheel delta left over from the notches recorded
+    wheel_rest: (f64, f64),
+    #[serde(skip)]
+    /// state at the start of a new recording, the init state once it is kept
+    rec_init_state: Option<GlobalState>,
+    #[serde(skip)]
+    /// seek shortcut is held, to seek only once per press
+    seek_held: bool,
+    #[serde(skip)]
+    /// the playback loops, toggled while playing
+    looping: bool,
+    #[serde(skip)]
+    /// start of the listeners, the time of events
+    clock: Option<std::time::Instant>,
+    #[serde(skip)]
+    /// time of the last event, or of the last check without events
+    now_ms: f64,
+    #[serde(skip)]
+    /// the first playback is confirmed
+    playback_confirmed: bool,
+    #[serde(skip)]
+    /// start playback shortcut is held since the last confirm check
+    confirm_held: bool,
+    #[serde(skip)]
+    /// time until the start playback shortcut confirms
+    confirm_deadline: Option<f64>,
+    #[serde(skip)]
+    /// pressed keys, since when they are unchanged, and the length of the records then
+    stable_keys: (Vec<AnyKey>, f64, usize),
+    #[serde(skip)]
+    /// mouse position of the last event, for the deltas of camera mode
+    camera_last: Option<(f64, f64)>,
+    #[serde(skip)]
     rdev_thread: Option<JoinHandle<()>>,
+    #[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
     #[serde(skip)]
     controller_thread: Option<JoinHandle<()>>,
     #[serde(skip)]
     recv: Option<Receiver<CallbackType>>,
+    #[serde(skip)]
+    triggers: Vec<Box<dyn TriggerSource>>,
+    #[serde(skip)]
+    foreground: Option<Box<dyn ForegroundSource>>,
+    #[serde(skip)]
+    /// every event listened is written here as a JSON line
+    json_stream: Option<JsonStream>,
+    #[serde(skip)]
+    /// process whose config is applied, and the time of the last check
+    foreground_process: (Option<String>, f64),
 
     #[serde(skip)]
     pub state: RecorderState,
@@ -127,20 +625,72 @@ impl Default for Recorder {
     fn default() -> Self {
         Self {
             config: Config::new(),
-            // init_state: Default::default(),
+            init_state: Default::default(),
             records: Vec::new(),
+            bookmarks: BTreeMap::new(),
+            rumble_cues: Vec::new(),
+            clips: Vec::new(),
+            corrupt_clips: Vec::new(),
             player: RecordPlayer::new(),
             recorder: Default::default(),
             rec_pos: (0, 0, 0),
             rec_should_update: false,
+            wheel_rest: (0.0, 0.0),
+            rec_init_state: None,
+            seek_held: false,
+            looping: false,
+            clock: None,
+            now_ms: 0.0,
+            playback_confirmed: false,
+            confirm_held: false,
+            confirm_deadline: None,
+            stable_keys: (Vec::new(), 0.0, 0),
+            camera_last: None,
             rdev_thread: None,
             controller_thread: None,
             recv: None,
+            triggers: Vec::new(),
+            foreground: None,
+            json_stream: None,
+            foreground_process: (None, f64::NEG_INFINITY),
             state: RecorderState::Error,
         }
     }
 }
 
+#[cfg(all(windows, feature = "controller"))]
+impl PadSource for XInputHandle {
+    fn pad_state(&self, id: u32) -> Option<PadState> {
+        let state = self.get_state(id).ok()?;
+        let pad = &state.raw.Gamepad;
+        Some(PadState {
+            packet: state.raw.dwPacketNumber,
+            buttons: pad.wButtons,
+            trigger: (pad.bLeftTrigger, pad.bRightTrigger),
+            left_stick: (pad.sThumbLX, pad.sThumbLY),
+            right_stick: (pad.sThumbRX, pad.sThumbRY),
+        })
+    }
+}
+
+/// Rumble of the listened controllers.
+#[cfg(all(windows, feature = "controller"))]
+#[derive(Debug)]
+struct XInputRumble {
+    handle: XInputHandle,
+    ids: Vec<u32>,
+}
+
+#[cfg(all(windows, feature = "controller"))]
+impl RumbleSink for XInputRumble {
+    fn rumble(&mut self, left: u16, right: u16) {
+        for &id in &self.ids {
+            let _ = self.handle.set_state(id, left, right);
+        }
+    }
+}
+
+#[cfg(all(windows, feature = "controller"))]
 fn shake_all(handle: &XInputHandle) -> Vec<bool> {
     let res: Vec<_> = (0..4)
         .map(|i| handle.set_state(i, 40000, 40000).is_ok())
@@ -153,35 +703,96 @@ fn shake_all(handle: &XInputHandle) -> Vec<bool> {
 }
 
 impl Recorder {
+    /// Clips are loaded one by one, a clip failing to parse or to match its checksum
+    /// is skipped and listed in `corrupt_clips`.
+    /// A relative path is in `GMC_CONFIG_DIR` if set.
     pub fn from_file(path: String) -> Self {
-        std::fs::read_to_string(path).map_or_else(
-            |_| Self::default(),
-            |s| serde_yml::from_str(&s).unwrap_or_default(),
-        )
+        let Some(mut value) = std::fs::read_to_string(config_path(&path))
+            .ok()
+            .and_then(|s| serde_yml::from_str::<serde_yml::Value>(&s).ok())
+        else {
+            return Self::default();
+        };
+        let clips = value.as_mapping_mut().and_then(|m| m.remove("clips"));
+        let mut res: Self = serde_yml::from_value(value).unwrap_or_default();
+        if !res.config.raw_init_state {
+            let removed = res.init_state.dedup_pressed();
+            if !removed.is_empty() {
+                warn!("Removed duplicated init pressed keys: {removed:?}");
+            }
+        }
+        if let Some(file) = res.config.records_file.as_deref().map(config_path) {
+            if let Err(e) = res.import_records(file.clone()) {
+                warn!("Cannot load records from {file}: {e}");
+            }
+        }
+        let Some(serde_yml::Value::Sequence(clips)) = clips else {
+            return res;
+        };
+        for (index, clip) in clips.into_iter().enumerate() {
+            let name = clip.get("name").and_then(|n| n.as_str()).map(String::from);
+            match serde_yml::from_value::<Clip>(clip) {
+                Ok(clip) if clip.is_intact() => res.clips.push(clip),
+                result => {
+                    let name = name.unwrap_or_else(|| format!("#{index}"));
+                    match result {
+                        Ok(_) => warn!("Skip clip {name}, checksum mismatch"),
+                        Err(e) => warn!("Skip clip {name}, cannot parse: {e}"),
+                    }
+                    res.corrupt_clips.push(name);
+                }
+            }
+        }
+        res
     }
+    /// A relative path is in `GMC_CONFIG_DIR` if set.
     pub fn save_to_file(&self, path: String) {
+        let path = config_path(&path);
         warn!("Save to file {path}!");
-        let s = serde_yml::to_string(&self).unwrap();
+        let s = if let Some(file) = self.config.records_file.as_deref().map(config_path) {
+            match self.export_records(file.clone()) {
+                Ok(()) => {
+                    let mut value = serde_yml::to_value(self).unwrap();
+                    value["records"] = serde_yml::Value::Sequence(vec![]);
+                    serde_yml::to_string(&value).unwrap()
+                }
+                Err(e) => {
+                    warn!("Cannot save records to {file}: {e}, saved in {path}");
+                    serde_yml::to_string(&self).unwrap()
+                }
+            }
+        } else if self.config.columnar {
+            let mut value = serde_yml::to_value(self).unwrap();
+            value["records"] =
+                serde_yml::to_value(ColumnarRecords::from(&self.records[..])).unwrap();
+            serde_yml::to_string(&value).unwrap()
+        } else {
+            serde_yml::to_string(&self).unwrap()
+        };
         std::fs::write(path, s).unwrap();
     }
 
-    pub fn init(&mut self) {
+    /// Start the listeners and the player. On failure the recorder is in
+    /// `RecorderState::Error`, so `is_ok` is false.
+    pub fn init(&mut self) -> Result<(), String> {
         self.state = RecorderState::Ready;
 
         // 创建一个用于发送的通道
         let (tx, rx) = std::sync::mpsc::channel::<CallbackType>();
         // 传递给闭包的起始时间点
         let start_time = std::time::Instant::now();
+        self.clock = Some(start_time);
 
         // 键盘鼠标监听器
         if self.config.enable_keyboard || self.config.enable_mouse {
             let use_mouse = self.config.enable_mouse;
             let use_key = self.config.enable_keyboard;
             let tx = tx.clone();
+            let failed = tx.clone();
             let th = std::thread::spawn(move || {
                 // 假设这是我们要传递给闭包的起始时间点
                 let start_time = start_time.clone();
-                rdev::listen(move |e| {
+                let res = rdev::listen(move |e| {
                     // filter skip by enables
                     match e.event_type {
                         rdev::EventType::KeyPress(_) | rdev::EventType::KeyRelease(_) => {
@@ -202,8 +813,13 @@ impl Recorder {
                     let elapsed_ms = start_time.elapsed().as_secs_f64() * 1000.0;
                     let ev = CallbackType::MK(elapsed_ms, e.event_type, e.name.unwrap_or_default());
                     tx.send(ev).expect("Failed to send time");
-                })
-                .expect("Cannot create listener!");
+                });
+                // a listener that started never returns
+                if let Err(e) = res {
+                    let _ = failed.send(CallbackType::ListenerFailed(format!(
+                        "Cannot create listener: {e:?}"
+                    )));
+                }
             });
             self.rdev_thread.replace(th);
         }
@@ -217,61 +833,145 @@ impl Recorder {
             .filter_map(|(i, b)| if *b { Some(i as u32) } else { None })
             .collect();
         if !uses.is_empty() {
-            let interval = (self.config.interval * 1000.0) as u64;
-            let th = std::thread::spawn(move || {
-                let handle = XInputHandle::load_default().unwrap();
-                // just to test
-                let enabled = shake_all(&handle);
-                println!("Connection State: {:?}", enabled);
-                let mut controllers = vec![ControllerRaw::default(); 4];
-                loop {
-                    if interval > 0 {
-                        std::thread::sleep(std::time::Duration::from_micros(interval));
-                    }
-                    let elapsed_ms = start_time.elapsed().as_secs_f64() * 1000.0;
-                    for &i in uses.iter() {
-                        if let Ok(state) = handle.get_state(i) {
-                            let ctr = &mut controllers[i as usize];
-                            if state.raw.dwPacketNumber == ctr.pack_num {
-                                // not updated
-                                continue;
-                            }
-                            let pad = &state.raw.Gamepad;
-                            if pad.bLeftTrigger != ctr.tri.0 || pad.bRightTrigger != ctr.tri.1 {
-                                let ev = ctr.trigger_change(pad.bLeftTrigger, pad.bRightTrigger);
-                                tx.send(CallbackType::Ctrl(elapsed_ms, i, ev)).unwrap();
-                            }
-                            if pad.sThumbLX != ctr.sticker.0 || pad.sThumbLY != ctr.sticker.1 {
-                                let ev = ctr.sl_change(pad.sThumbLX, pad.sThumbLY);
-                                tx.send(CallbackType::Ctrl(elapsed_ms, i, ev)).unwrap();
-                            }
-                            if pad.sThumbRX != ctr.sticker.2 || pad.sThumbRY != ctr.sticker.3 {
-                                let ev = ctr.sr_change(pad.sThumbRX, pad.sThumbRY);
-                                tx.send(CallbackType::Ctrl(elapsed_ms, i, ev)).unwrap();
-                            }
-                            if pad.wButtons != ctr.button {
-                                let actions = ctr.btn_change(pad.wButtons);
-                                for ev in actions {
-                                    tx.send(CallbackType::Ctrl(elapsed_ms, i, ev)).unwrap();
-                                }
-                            }
-                        }
-                    }
-                }
-            });
-            self.controller_thread.replace(th);
+            #[cfg(all(windows, feature = "controller"))]
+            if let Err(e) = self.listen_controllers(uses, tx, start_time) {
+                warn!("{e}, controllers are not recorded.");
+            }
+            #[cfg(not(all(windows, feature = "controller")))]
+            warn!("Controllers {uses:?} need the `controller` feature on Windows, ignored.");
         }
         self.recv.replace(rx);
+        if self.foreground.is_none() {
+            self.foreground = Some(Box::new(SystemForeground));
+        }
+        if let Some(config) = self.config.midi_trigger.clone() {
+            #[cfg(feature = "midi")]
+            match crate::trigger::MidiTrigger::connect(config) {
+                Ok(trigger) => self.triggers.push(Box::new(trigger)),
+                Err(e) => warn!("Cannot connect MIDI trigger: {e}"),
+            }
+            #[cfg(not(feature = "midi"))]
+            warn!("MIDI trigger {:?} needs the `midi` feature.", config.port);
+        }
+
+        if let Err(e) = self.player.init() {
+            return self.fail(e);
+        }
+        self.init_rumble();
+        Ok(())
+    }
+
+    /// Poll the controllers of uses on a thread, sending their events to tx.
+    #[cfg(all(windows, feature = "controller"))]
+    fn listen_controllers(
+        &mut self,
+        uses: Vec<u32>,
+        tx: std::sync::mpsc::Sender<CallbackType>,
+        start_time: std::time::Instant,
+    ) -> Result<(), String> {
+        if let Err(e) = XInputHandle::load_default() {
+            return Err(format!(
+                "Cannot load XInput for controllers {uses:?}: {e:?}"
+            ));
+        }
+        let interval = self.config.poll_interval();
+        let high_res_timer = self.config.high_res_timer;
+        let stick_sensitivity = self.config.stick_sensitivity;
+        let circular_normalize = self.config.circular_normalize;
+        let split_triggers = self.config.split_triggers;
+        let th = std::thread::spawn(move || {
+            let handle = match XInputHandle::load_default() {
+                Ok(handle) => handle,
+                Err(e) => return warn!("Cannot load XInput: {e:?}"),
+            };
+            // just to test
+            let enabled = shake_all(&handle);
+            println!("Connection State: {:?}", enabled);
+            let controller = ControllerRaw {
+                split_triggers,
+                ..ControllerRaw::new(stick_sensitivity, circular_normalize)
+            };
+            let mut controllers = vec![controller; 4];
+            let mut timer = PollTimer::new(interval, high_res_timer);
+            loop {
+                timer.wait();
+                let elapsed_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+                for (i, ev) in poll_controllers(&handle, &uses, &mut controllers) {
+                    tx.send(CallbackType::Ctrl(elapsed_ms, i, ev)).unwrap();
+                }
+            }
+        });
+        self.controller_thread.replace(th);
+        Ok(())
+    }
+
+    /// Stop for the reason, `is_ok` is false afterwards.
+    fn fail(&mut self, reason: String) -> Result<(), String> {
+        warn!("{reason}");
+        self.state = RecorderState::Error;
+        Err(reason)
+    }
+
+    /// Play the rumble cues on the listened controllers, none without the `controller` feature.
+    fn init_rumble(&mut self) {
+        #[cfg(all(windows, feature = "controller"))]
+        {
+            let ids: Vec<u32> = (0..4)
+                .filter(|&i| self.config.enable_controller[i as usize])
+                .collect();
+            if !ids.is_empty() {
+                match XInputHandle::load_default() {
+                    Ok(handle) => self
+                        .player
+                        .set_rumble_sink(Box::new(XInputRumble { handle, ids })),
+                    Err(e) => warn!("Cannot load XInput for rumble cues: {e:?}"),
+                }
+            }
+        }
+    }
+
+    /// Write the event to the JSON stream, dropped on the first failure.
+    fn stream(&mut self, event: StreamEvent) {
+        let Some(JsonStream(out)) = &mut self.json_stream else {
+            return;
+        };
+        let res = serde_json::to_writer(&mut *out, &event)
+            .map_err(std::io::Error::from)
+            .and_then(|()| writeln!(out))
+            .and_then(|()| out.flush());
+        if let Err(e) = res {
+            warn!("Stop streaming events: {e}");
+            self.json_stream = None;
+        }
+    }
 
-        self.player.init();
+    /// The raw wheel delta with the rest of the last ones in whole notches of
+    /// `wheel_notch_size`, None if less than half a notch. The rest is kept for the next delta.
+    fn wheel_notches(&mut self, dx: f64, dy: f64) -> Option<(f64, f64)> {
+        let size = match self.config.wheel_notch_size {
+            size if size > 0.0 => size,
+            _ => 1.0,
+        };
+        let (x, y) = (self.wheel_rest.0 + dx, self.wheel_rest.1 + dy);
+        let notches = ((x / size).round(), (y / size).round());
+        self.wheel_rest = (x - notches.0 * size, y - notches.1 * size);
+        (notches != (0.0, 0.0)).then_some(notches)
     }
 
+    /// Wait for the next input event and record it.
+    ///
+    /// Returns without an event after 1/60s, so that the main loop also polls triggers.
     pub fn listen(&mut self) {
         let r = self.recv.as_ref().unwrap();
-        match r.recv() {
+        match r.recv_timeout(std::time::Duration::from_millis(1000 / 60)) {
             Ok(CallbackType::MK(ms, ev, s)) => {
                 info!("MK:ms={:.2}\ts={:?}\t{:?}", ms, s, ev);
-                if ms > self.recorder.time_ms + 1.0 || self.rec_should_update {
+                self.stream(StreamEvent::Input { ms, event: &ev });
+                self.now_ms = ms;
+                if ms > self.recorder.time_ms + 1.0
+                    || self.rec_should_update
+                    || self.config.no_coalesce
+                {
                     self.next_ms(ms);
                 }
                 match ev {
@@ -279,6 +979,12 @@ impl Recorder {
                     rdev::EventType::KeyRelease(key) => self.recorder.key_up(key.into()),
                     rdev::EventType::ButtonPress(button) => self.recorder.key_down(button.into()),
                     rdev::EventType::ButtonRelease(button) => self.recorder.key_up(button.into()),
+                    rdev::EventType::MouseMove { x, y } if self.config.camera_mode => {
+                        // raw pixels, relative moves are not affected by the screen scale
+                        if let Some((lx, ly)) = self.camera_last.replace((x, y)) {
+                            self.recorder.moves(AnyOffset::MouseDelta(x - lx, y - ly));
+                        }
+                    }
                     rdev::EventType::MouseMove { x, y } => self.recorder.moves(AnyOffset::Mouse(
                         x / self.config.screen_scale,
                         y / self.config.screen_scale,
@@ -286,12 +992,18 @@ impl Recorder {
                     rdev::EventType::Wheel {
                         delta_x: x,
                         delta_y: y,
-                    } => self.recorder.moves(AnyOffset::Wheel(x as f64, y as f64)),
+                    } => {
+                        if let Some((x, y)) = self.wheel_notches(x as f64, y as f64) {
+                            self.recorder.moves(AnyOffset::Wheel(x, y));
+                        }
+                    }
                 }
             }
             Ok(CallbackType::Ctrl(ms, id, ev)) => {
                 info!("C{id}:ms={:.2}\t{:?}", ms, ev);
-                if ms > self.recorder.time_ms + 1.0 {
+                self.stream(StreamEvent::Controller { ms, id, event: &ev });
+                self.now_ms = ms;
+                if ms > self.recorder.time_ms + 1.0 || self.config.no_coalesce {
                     self.next_ms(ms);
                 }
                 match ev {
@@ -306,6 +1018,12 @@ impl Recorder {
                     ControllerEvent::TriggerMove(x, y) => {
                         self.recorder.moves(AnyOffset::Trigger(id, x, y))
                     }
+                    ControllerEvent::LeftTriggerMove(x) => {
+                        self.recorder.moves(AnyOffset::LeftTrigger(id, x))
+                    }
+                    ControllerEvent::RightTriggerMove(y) => {
+                        self.recorder.moves(AnyOffset::RightTrigger(id, y))
+                    }
                     ControllerEvent::LSticksMove(x, y) => {
                         self.recorder.moves(AnyOffset::LeftStick(id, x, y))
                     }
@@ -314,6 +1032,14 @@ impl Recorder {
                     }
                 }
             }
+            Ok(CallbackType::ListenerFailed(reason)) => {
+                let _ = self.fail(reason);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(clock) = self.clock {
+                    self.now_ms = clock.elapsed().as_secs_f64() * 1000.0;
+                }
+            }
             Err(e) => panic!("Receiver Error! {e}"),
         }
     }
@@ -322,8 +1048,128 @@ impl Recorder {
         self.state != RecorderState::Error
     }
 
+    /// Process the pending signals of the trigger sources.
+    pub fn match_triggers(&mut self) -> RecorderState {
+        let signals: Vec<_> = self
+            .triggers
+            .iter_mut()
+            .flat_map(|t| std::iter::from_fn(|| t.poll()))
+            .collect();
+        for signal in signals {
+            info!("Trigger {:?} in state {:?}", signal, self.state);
+            match (signal, &self.state) {
+                (TriggerSignal::Start, RecorderState::Ready) => self.start_record(0),
+                (TriggerSignal::Stop, RecorderState::Recording) => self.stop_record(false),
+                (TriggerSignal::Stop, RecorderState::Playing) => self.stop_playback(),
+                (TriggerSignal::Save, _) => self.save_to_file("config.yaml".to_string()),
+                _ => (),
+            }
+        }
+        self.state.clone()
+    }
+
+    /// Swap in the config registered for the foreground process.
+    ///
+    /// Checked at most every 500ms, and only while Ready so that a recording
+    /// or playback is never interrupted. Settings used by `init` (interval and enables)
+    /// are not affected until next launch.
+    pub fn watch_foreground(&mut self) {
+        if self.config.game_configs.is_empty() || self.state != RecorderState::Ready {
+            return;
+        }
+        let Some(source) = &self.foreground else {
+            return;
+        };
+        if self.now_ms < self.foreground_process.1 + 500.0 {
+            return;
+        }
+        self.foreground_process.1 = self.now_ms;
+        let Some(name) = source.process_name() else {
+            return;
+        };
+        if self.foreground_process.0.as_ref() == Some(&name) {
+            return;
+        }
+        if let Some(path) = self.config.game_config(&name) {
+            let path = &config_path(path);
+            match Config::load(path) {
+                Ok(mut config) => {
+                    warn!("Load config {path} for {name}!");
+                    config.game_configs = std::mem::take(&mut self.config.game_configs);
+                    self.apply_config(config);
+                }
+                Err(e) => warn!("Cannot load config {path} for {name}: {e}"),
+            }
+        }
+        self.foreground_process.0 = Some(name);
+    }
+
+    /// Swap in the config, keeping the records.
+    /// Settings of the listening threads, like `enable_controller`, take effect on the next `init`.
+    pub fn apply_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    /// Seek the player to the bookmark, returns false if there is no such bookmark.
+    pub fn jump_to_bookmark(&mut self, name: &str) -> bool {
+        let Some(&ms) = self.bookmarks.get(name) else {
+            warn!("No bookmark named {name}");
+            return false;
+        };
+        self.player.seek_to_ms(ms);
+        true
+    }
+    fn matched_bookmark(&self, pat: &ShortCut) -> Option<String> {
+        self.config
+            .jump_to_bookmark
+            .iter()
+            .find(|(_, shortcuts)| self.match_stable(pat, shortcuts))
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Whether the playback may start, the first playback after launch needs the shortcut
+    /// pressed again within `PLAYBACK_CONFIRM_MS` with `require_playback_confirmation`.
+    fn confirm_playback(&mut self) -> bool {
+        if !self.config.require_playback_confirmation || self.playback_confirmed {
+            return true;
+        }
+        if self.confirm_held {
+            return false;
+        }
+        self.confirm_held = true;
+        match self.confirm_deadline {
+            Some(deadline) if self.now_ms <= deadline => {
+                warn!("Playback confirmed.");
+                self.playback_confirmed = true;
+                true
+            }
+            _ => {
+                warn!(
+                    "Press the start playback shortcut again within {}s to confirm playback!",
+                    PLAYBACK_CONFIRM_MS / 1000.0
+                );
+                self.confirm_deadline = Some(self.now_ms + PLAYBACK_CONFIRM_MS);
+                false
+            }
+        }
+    }
+
+    /// Match the shortcuts once the pressed keys are unchanged for `shortcut_stable_ms`.
+    fn match_stable(&self, pat: &ShortCut, shortcuts: &ShortCuts) -> bool {
+        self.now_ms - self.stable_keys.1 >= self.config.shortcut_stable_ms
+            && self
+                .recorder
+                .match_shortcuts(pat, shortcuts, self.config.stick_deadzone)
+    }
+
     fn next_ms(&mut self, ms: f64) {
         self.rec_should_update = false;
+        if let Some(idle) = self.config.auto_pause_idle_ms {
+            if self.state == RecorderState::Recording && ms - self.recorder.time_ms > idle {
+                info!("Skip {:.2}ms idle in recording", ms - self.recorder.time_ms);
+                self.recorder.skip_rec(ms, IDLE_GAP_MS);
+            }
+        }
         let e = self.recorder.next_ms(ms);
         if self.state == RecorderState::Recording {
             self.records.push(e);
@@ -334,80 +1180,112 @@ impl Recorder {
         self.records.pop();
         self.recorder.clear_this();
     }
+    /// Drop the entry that completed the shortcut, and the ones pushed after it
+    /// while waiting for `shortcut_stable_ms`.
+    fn clear_shortcut(&mut self) {
+        self.rec_should_update = false;
+        self.records.truncate(self.stable_keys.2.saturating_sub(1));
+        self.recorder.clear_this();
+    }
 
     pub fn match_shortcuts(&mut self) -> RecorderState {
-        let pat = self.recorder.get_pattern();
+        if self.recorder.pressed_keys != self.stable_keys.0 {
+            self.stable_keys = (
+                self.recorder.pressed_keys.clone(),
+                self.now_ms,
+                self.records.len(),
+            );
+        }
+        let pat = self
+            .recorder
+            .get_pattern(self.config.distinguish_lr_modifiers);
         debug!(
             "Pattern: {:?}({}+{})",
             pat, pat.key_option, pat.controller_btn_option
         );
         debug!("Pressed: {:?}", self.recorder.pressed_keys);
-        if self
-            .recorder
-            .match_shortcuts(&pat, &self.config.save_records)
-        {
+        if self.match_stable(&pat, &self.config.save_records) {
             self.save_to_file("config.yaml".to_string());
         }
         match self.state {
             RecorderState::Ready => {
-                if self
-                    .recorder
-                    .match_shortcuts(&pat, &self.config.append_record)
-                {
+                if self.match_stable(&pat, &self.config.save_and_new) {
+                    info!("Save and New Rec.");
+                    // no entry of the shortcut to pop while ready, keep the records whole
+                    self.recorder.clear_this();
+                    self.save_and_new()
+                } else if self.match_stable(&pat, &self.config.append_record) {
                     info!("Append Rec.");
-                    self.clear_this();
-                    self.start_record(self.records.len())
-                } else if self
-                    .recorder
-                    .match_shortcuts(&pat, &self.config.start_record)
-                {
+                    self.clear_shortcut();
+                    self.append_record()
+                } else if self.match_stable(&pat, &self.config.start_record) {
                     info!("New Rec.");
-                    self.clear_this();
+                    self.clear_shortcut();
                     self.start_record(0)
-                } else if self
-                    .recorder
-                    .match_shortcuts(&pat, &self.config.start_playback)
-                {
-                    self.clear_this();
-                    self.start_playback()
+                } else if self.match_stable(&pat, &self.config.start_playback) {
+                    if self.confirm_playback() {
+                        self.clear_shortcut();
+                        self.start_playback()
+                    }
+                } else {
+                    self.confirm_held = false;
                 }
             }
             RecorderState::Recording => {
-                if self
-                    .recorder
-                    .match_shortcuts(&pat, &self.config.drop_record)
-                {
-                    self.clear_this();
+                if self.match_stable(&pat, &self.config.drop_record) {
+                    self.clear_shortcut();
                     self.stop_record(true)
-                } else if self
-                    .recorder
-                    .match_shortcuts(&pat, &self.config.stop_record)
-                {
-                    self.clear_this();
+                } else if self.match_stable(&pat, &self.config.stop_record) {
+                    self.clear_shortcut();
                     self.stop_record(false)
                 }
             }
             RecorderState::Playing => {
-                if self.player.is_done() {
+                if self.config.player_watchdog_ms > 0.0
+                    && self.player.watchdog(self.config.player_watchdog_ms)
+                {
+                    warn!("Player restarted, playback stopped.");
+                    self.init_rumble();
+                    self.clear_this();
+                    self.stop_playback();
+                } else if self.player.is_done() {
                     warn!("Player is done.");
                     self.clear_this();
                     self.stop_playback();
-                } else if self
-                    .recorder
-                    .match_shortcuts(&pat, &self.config.continue_record)
-                {
+                } else if self.match_stable(&pat, &self.config.continue_record) {
                     warn!("Stop play for recording.");
-                    self.clear_this();
+                    self.clear_shortcut();
                     let pos = self.player.get_progress();
                     self.stop_playback();
                     self.start_record(pos);
-                } else if self
-                    .recorder
-                    .match_shortcuts(&pat, &self.config.stop_playback)
-                {
-                    self.clear_this();
+                } else if self.match_stable(&pat, &self.config.stop_playback) {
+                    self.clear_shortcut();
                     warn!("{:?}", self.recorder.pressed_keys);
                     self.stop_playback();
+                } else if self.match_stable(&pat, &self.config.seek_forward) {
+                    if !self.seek_held {
+                        self.player.seek_ms(self.config.seek_step_ms);
+                    }
+                    self.seek_held = true;
+                } else if self.match_stable(&pat, &self.config.seek_back) {
+                    if !self.seek_held {
+                        self.player.seek_ms(-self.config.seek_step_ms);
+                    }
+                    self.seek_held = true;
+                } else if self.match_stable(&pat, &self.config.toggle_loop) {
+                    if !self.seek_held {
+                        self.looping = !self.looping;
+                        warn!("Loop playback: {}", self.looping);
+                        self.player.set_loop(self.looping);
+                    }
+                    self.seek_held = true;
+                } else if let Some(name) = self.matched_bookmark(&pat) {
+                    if !self.seek_held {
+                        self.jump_to_bookmark(&name);
+                    }
+                    self.seek_held = true;
+                } else {
+                    self.seek_held = false;
                 }
             }
             RecorderState::Error => (),
@@ -417,56 +1295,1359 @@ impl Recorder {
     }
 }
 
+#[allow(unused)]
+/// this is implement of the api for embedding the recorder
 impl Recorder {
-    fn start_record(&mut self, continue_at: usize) {
-        warn!("Start Recording!!! Continued at:{}", continue_at);
-        if continue_at == 0 {
-            self.rec_pos = (0, self.records.len(), 0);
-            self.recorder.start_rec(0.0);
-        } else {
-            self.rec_pos = (continue_at, self.records.len(), 0);
-            self.recorder.start_rec(self.records[continue_at - 1].ms);
+    /// Keys currently held, in pressing order.
+    ///
+    /// Updated only by `listen`, which takes `&mut self`, so a reader on another thread
+    /// has to share the recorder behind a lock anyway.
+    pub fn pressed_keys(&self) -> &[AnyKey] {
+        &self.recorder.pressed_keys
+    }
+    /// Current mouse position, wheel, triggers and sticks.
+    pub fn current_offsets(&self) -> &AllOffsets {
+        &self.recorder.offsets
+    }
+    /// The player is idle, or made progress within `player_watchdog_ms` while playing.
+    pub fn player_healthy(&self) -> bool {
+        self.config.player_watchdog_ms <= 0.0
+            || self.player.is_healthy(self.config.player_watchdog_ms)
+    }
+
+    /// Write every event listened to out as a JSON line, besides recording it.
+    pub fn stream_json(&mut self, out: Box<dyn std::io::Write + Send>) {
+        self.json_stream = Some(JsonStream(out));
+    }
+    /// Play the records into the buffer of the sink, drained by the host, instead of
+    /// simulating the input. Set after `init`, and again after a watchdog restart.
+    pub fn play_into(&mut self, engine: EngineSink) {
+        self.player.set_engine_sink(Some(engine));
+    }
+
+    /// Add an external source of start/stop/save signals, polled by `match_triggers`.
+    pub fn add_trigger(&mut self, source: Box<dyn TriggerSource>) {
+        self.triggers.push(source);
+    }
+
+    pub fn add_bookmark(&mut self, name: String, ms: f64) {
+        info!("Add bookmark {name} at {ms}ms");
+        self.bookmarks.insert(name, ms);
+    }
+    /// Discard the recording in progress and return to Ready, the records stay as they were
+    /// before recording started. Does nothing when not recording.
+    pub fn cancel_recording(&mut self) {
+        if self.state != RecorderState::Recording {
+            info!("No recording to cancel in state {:?}.", self.state);
+            return;
         }
-        warn!("Recorder pos: {:?}", self.rec_pos);
-        info!("Recorder pos: {:?}", self.rec_pos);
-        self.state = RecorderState::Recording;
+        self.rec_should_update = false;
+        self.recorder.clear_this();
+        self.stop_record(true);
     }
-    fn stop_record(&mut self, discard_records: bool) {
-        warn!("Stop Recording!!! Discard:{}", discard_records);
-        let mut rec = self.records.split_off(self.rec_pos.1);
-        warn!("Recorder pos: {:?}", self.rec_pos);
-        info!("Records length: {}", rec.len());
-        if !discard_records {
-            if self.rec_pos.0 == 0 {
-                self.records = rec;
-                info!("Records replaced with rec.");
-            } else {
-                let _ = self.records.split_off(self.rec_pos.0);
-                self.records.append(&mut rec);
-                info!("Records cut at rec.");
+
+    /// Save the records as a clip, replacing the clip of the same name.
+    /// Evicts the least recently used clips beyond `max_clips`.
+    pub fn save_clip(&mut self, name: String) {
+        let now = unix_ms();
+        let created = match self.clips.iter().position(|c| c.name == name) {
+            Some(index) => self.clips.remove(index).created,
+            None => now,
+        };
+        info!("Save clip {name} of {} records", self.records.len());
+        let checksum = Some(Clip::checksum(&name, &self.records));
+        self.clips.push(Clip {
+            name,
+            records: self.records.clone(),
+            created,
+            accessed: now,
+            checksum,
+        });
+        if let Some(max) = self.config.max_clips {
+            while self.clips.len() > max {
+                let clip = self.clips.remove(0);
+                warn!("Evict clip {}, last used at {}", clip.name, clip.accessed);
             }
         }
-        self.state = RecorderState::Ready;
     }
-    fn start_playback(&mut self) {
-        warn!("Start Playback!!!");
-        self.player.start_playback(&self.records);
-        self.state = RecorderState::Playing;
+    /// Replace the records with the clip, returns false if there is no such clip.
+    pub fn load_clip(&mut self, name: &str) -> bool {
+        let Some(index) = self.clips.iter().position(|c| c.name == name) else {
+            warn!("No clip named {name}");
+            return false;
+        };
+        let mut clip = self.clips.remove(index);
+        clip.accessed = unix_ms();
+        self.records = clip.records.clone();
+        self.clips.push(clip);
+        true
     }
-    fn stop_playback(&mut self) {
-        warn!("Stop Playback!!!");
-        self.player.stop_playback();
+    pub fn clip_names(&self) -> Vec<&str> {
+        self.clips.iter().map(|c| c.name.as_str()).collect()
+    }
+    /// Clips skipped when loading from file, by name or by `#index` if unnamed.
+    pub fn corrupt_clips(&self) -> &[String] {
+        &self.corrupt_clips
+    }
+}
+
+/// Subsystems checked by `self_test`, Ok if working or the reason why not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTestReport {
+    /// virtual controller for controller playback
+    pub vigem: Result<(), String>,
+    /// XInput for recording controllers
+    pub xinput: Result<(), String>,
+    /// keyboard and mouse listener
+    pub listener: Result<(), String>,
+    /// playing a tiny recording without simulating it
+    pub playback: Result<(), String>,
+}
+
+#[allow(unused)]
+impl SelfTestReport {
+    pub fn is_ok(&self) -> bool {
+        [&self.vigem, &self.xinput, &self.listener, &self.playback]
+            .iter()
+            .all(|r| r.is_ok())
+    }
+}
+
+impl std::fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Self test:")?;
+        let lines = [
+            ("ViGEm", &self.vigem),
+            ("XInput", &self.xinput),
+            ("listener", &self.listener),
+            ("playback", &self.playback),
+        ];
+        for (name, result) in lines {
+            match result {
+                Ok(()) => writeln!(f, "  {name}: ok")?,
+                Err(e) => writeln!(f, "  {name}: {e}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The drivers checked by `self_test`.
+pub trait SelfTestProbe {
+    fn connect_vigem(&self) -> Result<(), String>;
+    fn load_xinput(&self) -> Result<(), String>;
+    /// the display the keyboard and mouse listener hooks into
+    fn open_display(&self) -> Result<(), String>;
+}
+
+/// The drivers of the system.
+struct SystemProbe;
+
+impl SelfTestProbe for SystemProbe {
+    fn connect_vigem(&self) -> Result<(), String> {
+        player::probe_vigem()
+    }
+    fn load_xinput(&self) -> Result<(), String> {
+        #[cfg(all(windows, feature = "controller"))]
+        return XInputHandle::load_default()
+            .map(|_| ())
+            .map_err(|e| format!("Cannot load XInput: {e:?}"));
+        #[cfg(not(all(windows, feature = "controller")))]
+        Err("Built without controllers (the `controller` feature on Windows)".to_string())
+    }
+    fn open_display(&self) -> Result<(), String> {
+        rdev::display_size()
+            .map(|_| ())
+            .map_err(|e| format!("Cannot open the display to listen on: {e:?}"))
+    }
+}
+
+/// this is implement of the startup self test
+impl Recorder {
+    /// Check the subsystems before `init`, and turn off the controllers if XInput is missing.
+    /// Controller playback is skipped without ViGEm anyway. Starting a second listener
+    /// next to the one of `init` would get every event twice, so only its display is
+    /// checked here, and `listen` stops if the listener of `init` cannot start.
+    pub fn self_test(&mut self) -> SelfTestReport {
+        self.self_test_with(&SystemProbe)
+    }
+    fn self_test_with(&mut self, probe: &dyn SelfTestProbe) -> SelfTestReport {
+        let listener = match self.config.enable_keyboard || self.config.enable_mouse {
+            true => probe.open_display(),
+            false => Err("keyboard and mouse are not enabled".to_string()),
+        };
+        let report = SelfTestReport {
+            vigem: probe.connect_vigem(),
+            xinput: probe.load_xinput(),
+            listener,
+            playback: dry_run_playback(),
+        };
+        if report.xinput.is_err() && self.config.enable_controller.contains(&true) {
+            warn!("Controllers are not recorded without XInput.");
+            self.config.enable_controller = [false; 4];
+        }
+        report
+    }
+}
+
+/// Play a key tap into an `EngineSink`, without simulating it.
+fn dry_run_playback() -> Result<(), String> {
+    let key = AnyKey::from(rdev::Key::KeyA);
+    let records = [
+        RecordEntry {
+            ms: 0.0,
+            pressed: vec![key.clone()],
+            ..Default::default()
+        },
+        RecordEntry {
+            ms: 1.0,
+            released: vec![key],
+            ..Default::default()
+        },
+    ];
+    let mut player = RecordPlayer::new();
+    player.init_without_pad()?;
+    let engine = EngineSink::default();
+    player.set_engine_sink(Some(engine.clone()));
+    player.start_playback(&records);
+    let start = std::time::Instant::now();
+    while !player.is_done() && start.elapsed() < std::time::Duration::from_secs(1) {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    let played = engine.0.lock().unwrap().len();
+    match played {
+        2 => Ok(()),
+        n => Err(format!("Played {n} of 2 events")),
+    }
+}
+
+#[allow(unused)]
+/// this is implement of the transforms of records
+impl Recorder {
+    /// Shift every recorded mouse position by the delta, e.g. after moving the game window.
+    pub fn offset_mouse(&mut self, dx: f64, dy: f64) {
+        for offset in self.records.iter_mut().flat_map(|r| r.moves.iter_mut()) {
+            if let AnyOffset::Mouse(x, y) = offset {
+                (*x, *y) = (*x + dx, *y + dy);
+            }
+        }
+    }
+    /// Snap the gap between each pair of entries to a multiple of grid_ms, at least one
+    /// grid step if the entries were apart. The first entry keeps its time.
+    pub fn quantize_gaps(&mut self, grid_ms: f64) {
+        if grid_ms.is_nan() || grid_ms <= 0.0 {
+            warn!("Cannot quantize gaps to grid of {grid_ms}ms.");
+            return;
+        }
+        let mut last = self.records.first().map_or(0.0, |r| r.ms);
+        let mut last_quantized = last;
+        for record in self.records.iter_mut().skip(1) {
+            let gap = record.ms - last;
+            last = record.ms;
+            let steps = match gap > 0.0 {
+                true => (gap / grid_ms).round().max(1.0),
+                false => 0.0,
+            };
+            record.ms = last_quantized + steps * grid_ms;
+            last_quantized = record.ms;
+        }
+    }
+    /// Cap the playback at max_ms, counting the waits of entries. The first entry beyond
+    /// is pulled in to max_ms, so a huge gap before it is clamped, and later entries are
+    /// dropped. A wait running past max_ms is shortened.
+    pub fn clamp_duration(&mut self, max_ms: f64) {
+        if max_ms.is_nan() || max_ms < 0.0 {
+            warn!("Cannot clamp duration to {max_ms}ms.");
+            return;
+        }
+        // the waits before delay the entries
+        let mut delay = 0.0;
+        let mut clamped = false;
+        self.records.retain_mut(|record| {
+            if clamped {
+                return false;
+            }
+            let at = record.ms + delay;
+            if at > max_ms {
+                record.ms = max_ms - delay;
+                record.wait = None;
+                clamped = true;
+                return true;
+            }
+            if let Some(wait) = &mut record.wait {
+                *wait = wait.min(max_ms - at);
+                delay += *wait;
+            }
+            true
+        });
+    }
+    /// Overlay two recordings on the same timeline, entries within 1ms are combined.
+    ///
+    /// A key held in both is pressed by the first press and released by the last release,
+    /// so one recording never releases a key the other still holds.
+    pub fn layer(a: &[RecordEntry], b: &[RecordEntry]) -> Vec<RecordEntry> {
+        let mut entries: Vec<&RecordEntry> = a.iter().chain(b).collect();
+        entries.sort_by(|x, y| x.ms.total_cmp(&y.ms));
+        let mut res: Vec<RecordEntry> = Vec::new();
+        let mut held: Vec<(AnyKey, usize)> = Vec::new();
+        for entry in entries {
+            let merged = match res.last_mut() {
+                Some(last) if entry.ms <= last.ms + 1.0 => last,
+                _ => {
+                    res.push(RecordEntry {
+                        ms: entry.ms,
+                        ..Default::default()
+                    });
+                    res.last_mut().unwrap()
+                }
+            };
+            for key in entry.pressed.iter().filter(|k| !entry.repeated.contains(k)) {
+                match held.iter_mut().find(|h| &h.0 == key) {
+                    Some(h) => h.1 += 1,
+                    None => {
+                        held.push((key.clone(), 1));
+                        merged.pressed.push(key.clone());
+                    }
+                }
+            }
+            for key in &entry.released {
+                match held.iter().position(|h| &h.0 == key) {
+                    Some(i) if held[i].1 > 1 => held[i].1 -= 1,
+                    Some(i) => {
+                        held.remove(i);
+                        merged.released.push(key.clone());
+                    }
+                    // held since before the recording
+                    None => merged.released.push(key.clone()),
+                }
+            }
+            merged.moves.extend(entry.moves.iter().cloned());
+            merged.wait = match (merged.wait, entry.wait) {
+                (Some(x), Some(y)) => Some(x.max(y)),
+                (x, y) => x.or(y),
+            };
+        }
+        res
+    }
+    /// Keep only the entries from start_ms to end_ms, the first kept one at 0ms.
+    /// Keys held at the start are pressed by the first kept entry, keys still held at the
+    /// end are released by the last one.
+    pub fn trim(&mut self, start_ms: f64, end_ms: f64) {
+        if start_ms.is_nan() || end_ms.is_nan() || start_ms > end_ms {
+            warn!("Cannot trim to {start_ms}ms - {end_ms}ms.");
+            return;
+        }
+        let from = self.records.partition_point(|r| r.ms < start_ms);
+        let to = self.records.partition_point(|r| r.ms <= end_ms);
+        let mut held = Vec::new();
+        hold_keys(&mut held, &self.records[..from]);
+        let mut kept = self.records[from..to.max(from)].to_vec();
+        let Some(first_ms) = kept.first().map(|r| r.ms) else {
+            self.records.clear();
+            return;
+        };
+        let pressed = held.clone();
+        hold_keys(&mut held, &kept);
+        let first = &mut kept[0];
+        first.pressed.splice(
+            0..0,
+            pressed.into_iter().filter(|k| !first.released.contains(k)),
+        );
+        kept.last_mut().unwrap().released.extend(held);
+        for record in kept.iter_mut() {
+            record.ms -= first_ms;
+        }
+        self.records = kept;
+    }
+    /// Cut the entries from start_ms until end_ms, the later entries are moved earlier
+    /// by the cut time. Keys pressed or released in the cut and not back by its end are
+    /// pressed or released at the cut, so that none stays held or is released unpressed.
+    pub fn delete_range(&mut self, start_ms: f64, end_ms: f64) {
+        if start_ms.is_nan() || end_ms.is_nan() || start_ms > end_ms {
+            warn!("Cannot delete {start_ms}ms - {end_ms}ms.");
+            return;
+        }
+        let Some(first_ms) = self.records.first().map(|r| r.ms) else {
+            return;
+        };
+        let start_ms = start_ms.max(first_ms);
+        let from = self.records.partition_point(|r| r.ms < start_ms);
+        let to = self.records.partition_point(|r| r.ms < end_ms).max(from);
+        let mut before = Vec::new();
+        hold_keys(&mut before, &self.records[..from]);
+        let mut after = before.clone();
+        hold_keys(&mut after, &self.records[from..to]);
+        let cut = RecordEntry {
+            ms: start_ms,
+            pressed: after
+                .iter()
+                .filter(|k| !before.contains(k))
+                .cloned()
+                .collect(),
+            released: before
+                .iter()
+                .filter(|k| !after.contains(k))
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+        let removed = end_ms.max(start_ms) - start_ms;
+        let mut later = self.records.split_off(to);
+        self.records.truncate(from);
+        for record in later.iter_mut() {
+            record.ms -= removed;
+        }
+        if !cut.pressed.is_empty() || !cut.released.is_empty() {
+            self.records.push(cut);
+        }
+        self.records.extend(later);
+    }
+    /// Hold every key when it was released and release it when it was held, within the
+    /// time span of the records. Wheel and stick offsets are negated, mouse positions and
+    /// triggers are kept. Keys stay balanced, and inverting twice gives the original.
+    ///
+    /// A key tapped within one entry is released there and pressed again in an entry
+    /// inserted after it at the same ms, as an entry presses its keys before releasing
+    /// them. Inverting this gives the tap back, dropping the inserted entry.
+    pub fn invert(&mut self) {
+        let Some(last) = self.records.len().checked_sub(1) else {
+            return;
+        };
+        // entries only pressing keys at the ms of the previous entry, like the inserted ones
+        let spacers: Vec<bool> = (0..self.records.len())
+            .map(|i| {
+                let r = &self.records[i];
+                i > 0
+                    && r.ms == self.records[i - 1].ms
+                    && !r.pressed.is_empty()
+                    && r.released.is_empty()
+                    && r.moves.is_empty()
+                    && r.wait.is_none()
+            })
+            .collect();
+        // positions of the press and the release, and whether it is a tap
+        // (pressed and released in the same entry)
+        type Interval = (usize, usize, bool);
+        // held intervals of each key
+        let mut intervals: Vec<(AnyKey, Vec<Interval>)> = Vec::new();
+        let mut held: Vec<(AnyKey, usize)> = Vec::new();
+        let mut push = |key: AnyKey, interval| match intervals.iter_mut().find(|k| k.0 == key) {
+            Some((_, list)) => list.push(interval),
+            None => intervals.push((key, vec![interval])),
+        };
+        for (i, record) in self.records.iter_mut().enumerate() {
+            record.repeated.clear();
+            for key in record.pressed.drain(..) {
+                if !held.iter().any(|h| h.0 == key) {
+                    held.push((key, i));
+                }
+            }
+            for key in record.released.drain(..) {
+                // released without press was held since the start
+                let interval = match held.iter().position(|h| h.0 == key) {
+                    Some(index) => {
+                        let start = held.remove(index).1;
+                        (start, i, start == i)
+                    }
+                    None => (0, i, false),
+                };
+                push(key, interval);
+            }
+            for offset in record.moves.iter_mut() {
+                match offset {
+                    AnyOffset::Wheel(x, y)
+                    | AnyOffset::MouseDelta(x, y)
+                    | AnyOffset::LeftStick(_, x, y)
+                    | AnyOffset::RightStick(_, x, y) => (*x, *y) = (-*x, -*y),
+                    AnyOffset::Mouse(..)
+                    | AnyOffset::Trigger(..)
+                    | AnyOffset::LeftTrigger(..)
+                    | AnyOffset::RightTrigger(..) => (),
+                }
+            }
+        }
+        // still held at the end
+        for (key, start) in held {
+            push(key, (start, last, false));
+        }
+        // keys pressed by the entry inserted after each entry
+        let mut inserted: Vec<Vec<AnyKey>> = vec![Vec::new(); self.records.len()];
+        for (key, list) in intervals {
+            // released at `from`, pressed again after it if that was a tap
+            let (mut from, mut tap) = (0, false);
+            for (press, release, next_tap) in list.into_iter().chain([(last, last, false)]) {
+                if press <= from {
+                } else if tap {
+                    inserted[from].push(key.clone());
+                    self.records[press].released.push(key.clone());
+                } else if spacers[press] && press == from + 1 {
+                    // released for no time, the tap this was inverted from
+                    self.records[from].pressed.push(key.clone());
+                    self.records[from].released.push(key.clone());
+                } else {
+                    self.records[from].pressed.push(key.clone());
+                    self.records[press].released.push(key.clone());
+                }
+                (from, tap) = (release, next_tap);
+            }
+        }
+        let records = std::mem::take(&mut self.records);
+        for ((record, spacer), pressed) in records.into_iter().zip(spacers).zip(inserted) {
+            let ms = record.ms;
+            if !spacer || !record.pressed.is_empty() || !record.released.is_empty() {
+                self.records.push(record);
+            }
+            if !pressed.is_empty() {
+                self.records.push(RecordEntry {
+                    ms,
+                    pressed,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
+
+impl Recorder {
+    fn start_record(&mut self, continue_at: usize) {
+        warn!("Start Recording!!! Continued at:{}", continue_at);
+        if continue_at == 0 {
+            self.rec_pos = (0, self.records.len(), 0);
+            self.recorder.start_rec(0.0);
+            self.wheel_rest = (0.0, 0.0);
+            self.rec_init_state = Some(self.recorder.clone());
+        } else {
+            self.rec_pos = (continue_at, self.records.len(), 0);
+            self.recorder.start_rec(self.records[continue_at - 1].ms);
+        }
+        warn!("Recorder pos: {:?}", self.rec_pos);
+        info!("Recorder pos: {:?}", self.rec_pos);
+        self.state = RecorderState::Recording;
+    }
+    /// Keep the records in a file named by the time, e.g. `records-1700000000000.yaml`,
+    /// and start a new recording.
+    fn save_and_new(&mut self) {
+        let dir = std::path::Path::new(self.config.takes_dir.as_deref().unwrap_or(""));
+        let path = dir.join(format!("records-{:.0}.yaml", unix_ms()));
+        let path = config_path(&path.to_string_lossy());
+        if let Err(e) = self.save_take(&path) {
+            warn!("{e}");
+        }
+        self.start_record(0);
+    }
+    fn append_record(&mut self) {
+        let continue_at = self.records.len();
+        self.start_record(continue_at);
+        if continue_at > 0 {
+            let ms = self.records[continue_at - 1].ms + self.config.append_gap_ms;
+            self.recorder.start_rec(ms);
+        }
+    }
+    fn stop_record(&mut self, discard_records: bool) {
+        warn!("Stop Recording!!! Discard:{}", discard_records);
+        let mut rec = self.records.split_off(self.rec_pos.1);
+        warn!("Recorder pos: {:?}", self.rec_pos);
+        info!("Records length: {}", rec.len());
+        let init_state = self.rec_init_state.take();
+        if !discard_records {
+            if self.rec_pos.0 == 0 {
+                self.records = rec;
+                self.init_state = init_state.unwrap_or_default();
+                info!("Records replaced with rec.");
+            } else {
+                let _ = self.records.split_off(self.rec_pos.0);
+                self.records.append(&mut rec);
+                info!("Records cut at rec.");
+            }
+        }
+        self.state = RecorderState::Ready;
+    }
+    fn start_playback(&mut self) {
+        warn!("Start Playback!!!");
+        let records = self.playback_records();
+        self.player.set_options(self.playback_options());
+        self.looping = self.config.loop_playback;
+        self.player.set_loop(self.looping);
+        self.player.start_playback(&records);
+        self.state = RecorderState::Playing;
+    }
+    fn playback_options(&self) -> PlaybackOptions {
+        PlaybackOptions {
+            trailing_hold_ms: self.config.trailing_hold_ms,
+            batch_keyboard: self.config.batch_keyboard,
+            key_backends: self.config.key_backends.clone(),
+            max_mouse_speed_px_per_ms: self.config.max_mouse_speed_px_per_ms,
+            max_button_hold_ms: self.config.max_button_hold_ms,
+            playback_capture_path: self.config.playback_capture_path.clone(),
+            rumble_cues: {
+                let mut cues = self.rumble_cues.clone();
+                cues.sort_by(|a, b| a.0.total_cmp(&b.0));
+                cues
+            },
+            key_probability: self.config.key_probability.clone(),
+            random_seed: self.config.random_seed.unwrap_or_else(|| unix_ms() as u64),
+        }
+    }
+    /// records with the playback transforms of config applied,
+    /// entries are kept one to one so that positions match `self.records`.
+    fn playback_records(&self) -> Vec<RecordEntry> {
+        let mut records = match &self.config.mouse_to_controller {
+            Some(map) => map.apply(&self.records),
+            None => self.records.clone(),
+        };
+        if self.config.suppress_windows_key {
+            suppress_windows_key(&mut records);
+        }
+        if self.config.mirror_horizontal {
+            mirror_horizontal(&mut records, &self.config.mirror_keys);
+        }
+        if self.config.circular_normalize {
+            for offset in records.iter_mut().flat_map(|r| r.moves.iter_mut()) {
+                match offset {
+                    AnyOffset::LeftStick(_, x, y) | AnyOffset::RightStick(_, x, y) => {
+                        (*x, *y) = circle_to_square(*x, *y);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        records
+    }
+    fn stop_playback(&mut self) {
+        warn!("Stop Playback!!!");
+        self.player.stop_playback();
         self.player.set_progress(0);
         self.state = RecorderState::Ready;
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Remove Windows key taps without any other key pressed meanwhile,
+/// so that replaying them does not pop up the Start menu. Combos like `Win + D` are kept.
+fn suppress_windows_key(records: &mut [RecordEntry]) {
+    let is_meta = |k: &AnyKey| {
+        *k == AnyKey::from(rdev::Key::MetaLeft) || *k == AnyKey::from(rdev::Key::MetaRight)
+    };
+    // (key, pressed at, used in combo)
+    let mut held: Vec<(AnyKey, usize, bool)> = Vec::new();
+    // (key, pressed at, released at)
+    let mut lone = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+        for key in record.pressed.iter().filter(|k| is_meta(k)) {
+            held.push((key.clone(), i, false));
+        }
+        let combo = record
+            .pressed
+            .iter()
+            .any(|k| matches!(k, AnyKey::Keyboard(_)) && !is_meta(k));
+        if combo {
+            held.iter_mut().for_each(|h| h.2 = true);
+        }
+        for key in record.released.iter().filter(|k| is_meta(k)) {
+            if let Some(index) = held.iter().position(|h| &h.0 == key) {
+                let (key, at, combo) = held.remove(index);
+                if !combo {
+                    lone.push((key, at, Some(i)));
+                }
+            }
+        }
+    }
+    lone.extend(held.into_iter().filter(|h| !h.2).map(|h| (h.0, h.1, None)));
+    for (key, pressed, released) in lone {
+        debug!("Suppress {:?} pressed at: {}", key, pressed);
+        let record = &mut records[pressed];
+        if let Some(index) = record.pressed.iter().position(|k| k == &key) {
+            record.pressed.remove(index);
+        }
+        if let Some(released) = released {
+            let record = &mut records[released];
+            if let Some(index) = record.released.iter().position(|k| k == &key) {
+                record.released.remove(index);
+            }
+        }
+    }
+}
+
+/// Mirror the records left to right: the X of mouse deltas, horizontal wheel and sticks
+/// is negated, and the keys of each pair are swapped. Mouse positions are kept, the
+/// screen size is not known.
+fn mirror_horizontal(records: &mut [RecordEntry], pairs: &[(AnyKey, AnyKey)]) {
+    let swap = |key: &mut AnyKey| {
+        for (a, b) in pairs {
+            if key == a {
+                *key = b.clone();
+                return;
+            } else if key == b {
+                *key = a.clone();
+                return;
+            }
+        }
+    };
+    for record in records.iter_mut() {
+        record.pressed.iter_mut().for_each(swap);
+        record.released.iter_mut().for_each(swap);
+        record.repeated.iter_mut().for_each(swap);
+        for offset in record.moves.iter_mut() {
+            match offset {
+                AnyOffset::MouseDelta(x, _) | AnyOffset::Wheel(x, _) => *x = -*x,
+                AnyOffset::LeftStick(_, x, _) | AnyOffset::RightStick(_, x, _) => {
+                    *x = (-*x).clamp(-1.0, 1.0)
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// GUID that SDL assigns to every XInput device.
+const SDL_XINPUT_GUID: &str = "78696e70757401000000000000000000";
+/// XInput button flags with their binding in SDL's builtin `xinput` mapping.
+const SDL_XINPUT_BUTTONS: [(usize, &str); 15] = [
+    (0x1000, "a:b0"),
+    (0x2000, "b:b1"),
+    (0x4000, "x:b2"),
+    (0x8000, "y:b3"),
+    (0x0020, "back:b6"),
+    (0x0400, "guide:b10"),
+    (0x0010, "start:b7"),
+    (0x0040, "leftstick:b8"),
+    (0x0080, "rightstick:b9"),
+    (0x0100, "leftshoulder:b4"),
+    (0x0200, "rightshoulder:b5"),
+    (0x0001, "dpup:h0.1"),
+    (0x0002, "dpdown:h0.4"),
+    (0x0004, "dpleft:h0.8"),
+    (0x0008, "dpright:h0.2"),
+];
+
+/// Start of the binary records file, followed by the format version.
+const RECORDS_MAGIC: &[u8; 4] = b"GMCR";
+const RECORDS_VERSION: u32 = 1;
+
+/// Every field of an entry in order, bincode has no field names to tell skipped ones.
+type BinaryEntry = (
+    f64,
+    Vec<AnyKey>,
+    Vec<AnyKey>,
+    Vec<AnyOffset>,
+    Option<f64>,
+    Vec<AnyKey>,
+);
+
+/// A take of `save_and_new`, only the records.
+#[derive(Deserialize)]
+struct Take {
+    #[serde(deserialize_with = "deserialize_records")]
+    records: Vec<RecordEntry>,
+}
+
+#[allow(unused)]
+/// this is implement of storing records apart from the config
+impl Recorder {
+    /// Write only the records to path as yaml, columns of them with `columnar`.
+    /// The config, clips, bookmarks and `records_file` are left out.
+    fn save_take(&self, path: &str) -> Result<(), String> {
+        warn!("Save take to {path}!");
+        let records = match self.config.columnar {
+            true => serde_yml::to_value(ColumnarRecords::from(&self.records[..])),
+            false => serde_yml::to_value(&self.records),
+        };
+        let mut take = serde_yml::Mapping::new();
+        take.insert("records".into(), records.map_err(|e| e.to_string())?);
+        let s = serde_yml::to_string(&take).map_err(|e| e.to_string())?;
+        std::fs::write(path, s).map_err(|e| format!("Cannot write take {path}: {e}"))
+    }
+    /// Read the records of a take saved by `save_and_new`.
+    pub fn load_take(path: &str) -> Result<Vec<RecordEntry>, String> {
+        let s = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+        serde_yml::from_str::<Take>(&s)
+            .map(|take| take.records)
+            .map_err(|e| format!("Cannot parse take {path}: {e}"))
+    }
+    /// Write only the records to path, in bincode after a versioned header.
+    pub fn export_records(&self, path: String) -> Result<(), String> {
+        warn!("Export records to {path}!");
+        let entries: Vec<BinaryEntry> = self
+            .records
+            .iter()
+            .map(|r| {
+                let r = r.clone();
+                (r.ms, r.pressed, r.released, r.moves, r.wait, r.repeated)
+            })
+            .collect();
+        let mut bytes = RECORDS_MAGIC.to_vec();
+        bytes.extend_from_slice(&RECORDS_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(&entries).map_err(|e| e.to_string())?);
+        std::fs::write(path, bytes).map_err(|e| e.to_string())
+    }
+    /// Replace the records by the ones written by `export_records`.
+    /// The records are kept if the file is not of this format version.
+    pub fn import_records(&mut self, path: String) -> Result<(), String> {
+        let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+        let Some((magic, rest)) = bytes.split_first_chunk::<4>() else {
+            return Err(format!("{path} is not a records file"));
+        };
+        let Some((version, data)) = rest.split_first_chunk::<4>() else {
+            return Err(format!("{path} is not a records file"));
+        };
+        if magic != RECORDS_MAGIC {
+            return Err(format!("{path} is not a records file"));
+        }
+        let version = u32::from_le_bytes(*version);
+        if version != RECORDS_VERSION {
+            return Err(format!(
+                "{path} has records of version {version}, only {RECORDS_VERSION} is supported"
+            ));
+        }
+        let entries: Vec<BinaryEntry> = bincode::deserialize(data).map_err(|e| e.to_string())?;
+        self.records = entries
+            .into_iter()
+            .map(
+                |(ms, pressed, released, moves, wait, repeated)| RecordEntry {
+                    ms,
+                    pressed,
+                    released,
+                    moves,
+                    wait,
+                    repeated,
+                },
+            )
+            .collect();
+        Ok(())
+    }
+}
+
+#[allow(unused)]
+/// this is implement of exporting records to other formats
+impl Recorder {
+    /// SDL GameController mapping string for the controller buttons and axes used in records.
+    ///
+    /// Buttons are listed in the order of `SDL_XINPUT_BUTTONS`, followed by sticks and triggers.
+    pub fn export_sdl_mapping(&self) -> String {
+        let mut buttons = 0;
+        let (mut left_stick, mut right_stick, mut trigger) = (false, false, false);
+        for entry in &self.records {
+            for key in entry.pressed.iter().chain(entry.released.iter()) {
+                if let AnyKey::Controller(_, code) = key {
+                    buttons |= code;
+                }
+            }
+            for offset in &entry.moves {
+                match offset {
+                    AnyOffset::LeftStick(..) => left_stick = true,
+                    AnyOffset::RightStick(..) => right_stick = true,
+                    AnyOffset::Trigger(..)
+                    | AnyOffset::LeftTrigger(..)
+                    | AnyOffset::RightTrigger(..) => trigger = true,
+                    _ => (),
+                }
+            }
+        }
+        let mut res = format!("{SDL_XINPUT_GUID},XInput Controller,");
+        for (code, binding) in SDL_XINPUT_BUTTONS {
+            if buttons & code != 0 {
+                res += binding;
+                res += ",";
+            }
+        }
+        if left_stick {
+            res += "leftx:a0,lefty:a1,";
+        }
+        if right_stick {
+            res += "rightx:a3,righty:a4,";
+        }
+        if trigger {
+            res += "lefttrigger:a2,righttrigger:a5,";
+        }
+        res += "platform:Windows,";
+        res
+    }
+    /// Python script replaying the keyboard and mouse input of records with `pynput`,
+    /// timed by sleeps. Controller input cannot be replayed by `pynput`, and is written
+    /// as comments.
+    pub fn export_python(&self) -> String {
+        let mut res = String::from(PYTHON_HEADER);
+        let mut last_ms = self.records.first().map_or(0.0, |r| r.ms);
+        let mut delay = 0.0;
+        for entry in &self.records {
+            delay += entry.ms - last_ms;
+            last_ms = entry.ms;
+            if delay > 0.0 {
+                res += &format!("time.sleep({:.3})\n", delay / 1000.0);
+            }
+            let keys = entry
+                .pressed
+                .iter()
+                .map(|k| (k, "press"))
+                .chain(entry.released.iter().map(|k| (k, "release")));
+            for (key, action) in keys {
+                res += &match key {
+                    AnyKey::Keyboard(k) => match python_key(k.rdev_key()) {
+                        Some(k) => format!("keyboard.{action}({k})\n"),
+                        None => format!("# keyboard {action} {k:?} has no pynput key\n"),
+                    },
+                    AnyKey::MouseButton(btn) => match btn {
+                        0 => format!("mouse.{action}(Button.left)\n"),
+                        1 => format!("mouse.{action}(Button.right)\n"),
+                        2 => format!("mouse.{action}(Button.middle)\n"),
+                        _ => format!("# mouse {action} button {btn} has no pynput button\n"),
+                    },
+                    AnyKey::Controller(id, code) => {
+                        format!("# controller {id} {action} {code:#06x}\n")
+                    }
+                };
+            }
+            for offset in &entry.moves {
+                res += &match *offset {
+                    AnyOffset::Mouse(x, y) => format!("mouse.position = ({x:.0}, {y:.0})\n"),
+                    AnyOffset::MouseDelta(dx, dy) => format!("mouse.move({dx:.0}, {dy:.0})\n"),
+                    AnyOffset::Wheel(dx, dy) => format!("mouse.scroll({dx:.0}, {dy:.0})\n"),
+                    ref offset => format!("# controller {offset:?}\n"),
+                };
+            }
+            delay = entry.wait.unwrap_or(0.0);
+        }
+        res
+    }
+}
+
+/// Start of the script of `export_python`.
+const PYTHON_HEADER: &str = "\
+# Generated by game-movement-copy, replays the keyboard and mouse input with pynput.
+# Controller input is kept as comments only, pynput cannot emulate a controller.
+import time
+
+from pynput.keyboard import Controller as Keyboard, Key
+from pynput.mouse import Controller as Mouse, Button
+
+keyboard = Keyboard()
+mouse = Mouse()
+
+";
+
+/// The key as a pynput expression, a `Key` or a character.
+fn python_key(key: rdev::Key) -> Option<String> {
+    use rdev::Key::*;
+    let special = match key {
+        Alt => "alt_l",
+        AltGr => "alt_gr",
+        Backspace => "backspace",
+        CapsLock => "caps_lock",
+        ControlLeft => "ctrl_l",
+        ControlRight => "ctrl_r",
+        Delete => "delete",
+        DownArrow => "down",
+        End => "end",
+        Escape => "esc",
+        F1 => "f1",
+        F2 => "f2",
+        F3 => "f3",
+        F4 => "f4",
+        F5 => "f5",
+        F6 => "f6",
+        F7 => "f7",
+        F8 => "f8",
+        F9 => "f9",
+        F10 => "f10",
+        F11 => "f11",
+        F12 => "f12",
+        Home => "home",
+        LeftArrow => "left",
+        MetaLeft => "cmd_l",
+        MetaRight => "cmd_r",
+        PageDown => "page_down",
+        PageUp => "page_up",
+        Return | KpReturn => "enter",
+        RightArrow => "right",
+        ShiftLeft => "shift_l",
+        ShiftRight => "shift_r",
+        Space => "space",
+        Tab => "tab",
+        UpArrow => "up",
+        PrintScreen => "print_screen",
+        ScrollLock => "scroll_lock",
+        Pause => "pause",
+        NumLock => "num_lock",
+        Insert => "insert",
+        _ => "",
+    };
+    if !special.is_empty() {
+        return Some(format!("Key.{special}"));
+    }
+    let c = match key {
+        KeyA => 'a',
+        KeyB => 'b',
+        KeyC => 'c',
+        KeyD => 'd',
+        KeyE => 'e',
+        KeyF => 'f',
+        KeyG => 'g',
+        KeyH => 'h',
+        KeyI => 'i',
+        KeyJ => 'j',
+        KeyK => 'k',
+        KeyL => 'l',
+        KeyM => 'm',
+        KeyN => 'n',
+        KeyO => 'o',
+        KeyP => 'p',
+        KeyQ => 'q',
+        KeyR => 'r',
+        KeyS => 's',
+        KeyT => 't',
+        KeyU => 'u',
+        KeyV => 'v',
+        KeyW => 'w',
+        KeyX => 'x',
+        KeyY => 'y',
+        KeyZ => 'z',
+        Num0 | Kp0 => '0',
+        Num1 | Kp1 => '1',
+        Num2 | Kp2 => '2',
+        Num3 | Kp3 => '3',
+        Num4 | Kp4 => '4',
+        Num5 | Kp5 => '5',
+        Num6 | Kp6 => '6',
+        Num7 | Kp7 => '7',
+        Num8 | Kp8 => '8',
+        Num9 | Kp9 => '9',
+        BackQuote => '`',
+        Minus | KpMinus => '-',
+        Equal => '=',
+        LeftBracket => '[',
+        RightBracket => ']',
+        SemiColon => ';',
+        Quote => '\'',
+        BackSlash | IntlBackslash => '\\',
+        Comma => ',',
+        Dot | KpDelete => '.',
+        Slash | KpDivide => '/',
+        KpPlus => '+',
+        KpMultiply => '*',
+        _ => return None,
+    };
+    Some(match c {
+        '\'' | '\\' => format!("'\\{c}'"),
+        c => format!("'{c}'"),
+    })
+}
+
+/// Amount of input in records, for comparing the efficiency of recordings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EffortMetrics {
+    /// presses of keys, mouse buttons and controller buttons
+    pub key_presses: usize,
+    /// distance moved by all sticks, in full deflections
+    pub stick_travel: f64,
+    /// time from the first record to the last
+    pub duration_ms: f64,
+    /// key presses per minute of duration
+    pub actions_per_minute: f64,
+}
+
+#[allow(unused)]
+/// this is implement of analysing records
+impl Recorder {
+    pub fn effort_metrics(&self) -> EffortMetrics {
+        let mut res = EffortMetrics::default();
+        // last position of the left and right stick of each controller
+        let mut sticks: HashMap<(bool, u32), (f64, f64)> = HashMap::new();
+        for record in &self.records {
+            res.key_presses += record.pressed.len();
+            for offset in &record.moves {
+                let (stick, x, y) = match *offset {
+                    AnyOffset::LeftStick(id, x, y) => ((true, id), x, y),
+                    AnyOffset::RightStick(id, x, y) => ((false, id), x, y),
+                    _ => continue,
+                };
+                let last = sticks.insert(stick, (x, y)).unwrap_or_default();
+                res.stick_travel += (x - last.0).hypot(y - last.1);
+            }
+        }
+        if let (Some(first), Some(last)) = (self.records.first(), self.records.last()) {
+            res.duration_ms = last.ms - first.ms;
+        }
+        if res.duration_ms > 0.0 {
+            res.actions_per_minute = res.key_presses as f64 / (res.duration_ms / 60000.0);
+        }
+        res
+    }
+}
+
+/// A macro with named checkpoints, used to test a game against its input.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AssertionScript {
+    pub records: Vec<RecordEntry>,
+    /// (ms, tag) checkpoints, checked once every entry up to ms is played.
+    pub tags: Vec<(f64, String)>,
+}
+
+/// Results of the checkpoints of an `AssertionScript`, in playing order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssertionReport {
+    pub checkpoints: Vec<(String, bool)>,
+}
+
+#[allow(unused)]
+impl AssertionReport {
+    pub fn passed(&self) -> bool {
+        self.checkpoints.iter().all(|(_, pass)| *pass)
+    }
+}
+
+#[allow(unused)]
+/// this is implement of scripted playback
+impl Recorder {
+    /// Play the script and invoke the probe at every checkpoint.
+    ///
+    /// The script is played once, each checkpoint is probed as soon as the entries up to it
+    /// are played, so keys and sticks held across checkpoints stay held. Blocks until done.
+    /// The player has to be initialized and not be playing. A checkpoint not reached within
+    /// its time (and a second to spare) fails, as do all of them if the player is not started.
+    pub fn run_script_with_assertions(
+        &mut self,
+        script: &AssertionScript,
+        probe: Box<dyn Fn() -> bool>,
+    ) -> AssertionReport {
+        let mut tags = script.tags.clone();
+        tags.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut report = AssertionReport::default();
+        if !self.player.is_started() {
+            warn!("Cannot run script, the player is not started.");
+            report.checkpoints = tags.into_iter().map(|(_, tag)| (tag, false)).collect();
+            return report;
+        }
+        // the last entry is never reached, so nothing is released before the last checkpoint
+        let mut records = script.records.clone();
+        records.sort_by(|a, b| a.ms.total_cmp(&b.ms));
+        records.push(RecordEntry {
+            ms: f64::INFINITY,
+            ..Default::default()
+        });
+        self.player.set_loop(false);
+        self.player.start_playback(&records);
+        let start = std::time::Instant::now();
+        let mut pos = 0;
+        for (ms, tag) in tags {
+            let end = pos + records[pos..].partition_point(|r| r.ms <= ms);
+            let waits: f64 = records[..end].iter().filter_map(|r| r.wait).sum();
+            let deadline = start + std::time::Duration::from_secs_f64((ms + waits) / 1000.0 + 1.0);
+            while self.player.get_progress() < end && std::time::Instant::now() < deadline {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            let pass = match self.player.get_progress() >= end {
+                true => probe(),
+                false => {
+                    warn!("Checkpoint {tag} at {ms}ms not reached in time.");
+                    false
+                }
+            };
+            info!("Checkpoint {tag} at {ms}ms: {pass}");
+            report.checkpoints.push((tag, pass));
+            pos = end;
+        }
+        self.player.stop_playback();
+        self.player.set_loop(self.looping);
+        report
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
 pub struct RecordEntry {
     pub ms: f64,
     pub pressed: Vec<AnyKey>,
     pub released: Vec<AnyKey>,
     pub moves: Vec<AnyOffset>,
+    /// Explicit pause in ms after this entry, the following entries are delayed by it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait: Option<f64>,
+    /// Keys of `pressed` that were already held when pressed, i.e. auto-repeats.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repeated: Vec<AnyKey>,
+}
+
+/// Records stored column-wise, every field of the entries in its own list.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct ColumnarRecords {
+    pub ms: Vec<f64>,
+    pub pressed: Vec<Vec<AnyKey>>,
+    pub released: Vec<Vec<AnyKey>>,
+    pub moves: Vec<Vec<AnyOffset>>,
+    /// empty if no entry waits
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wait: Vec<Option<f64>>,
+    /// empty if no entry has repeated keys
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repeated: Vec<Vec<AnyKey>>,
+}
+
+impl From<&[RecordEntry]> for ColumnarRecords {
+    fn from(records: &[RecordEntry]) -> Self {
+        let mut res = Self::default();
+        for r in records {
+            res.ms.push(r.ms);
+            res.pressed.push(r.pressed.clone());
+            res.released.push(r.released.clone());
+            res.moves.push(r.moves.clone());
+            res.wait.push(r.wait);
+            res.repeated.push(r.repeated.clone());
+        }
+        if res.wait.iter().all(Option::is_none) {
+            res.wait.clear();
+        }
+        if res.repeated.iter().all(Vec::is_empty) {
+            res.repeated.clear();
+        }
+        res
+    }
+}
+
+impl TryFrom<ColumnarRecords> for Vec<RecordEntry> {
+    type Error = String;
+    fn try_from(columns: ColumnarRecords) -> Result<Self, String> {
+        let len = columns.ms.len();
+        let optional_len = |column_len| match column_len {
+            0 => len,
+            l => l,
+        };
+        if [
+            columns.pressed.len(),
+            columns.released.len(),
+            columns.moves.len(),
+            optional_len(columns.wait.len()),
+            optional_len(columns.repeated.len()),
+        ]
+        .into_iter()
+        .any(|l| l != len)
+        {
+            return Err(format!(
+                "Columns of records differ in length from ms ({len})"
+            ));
+        }
+        let mut wait = columns.wait.into_iter();
+        let mut repeated = columns.repeated.into_iter();
+        Ok(columns
+            .ms
+            .into_iter()
+            .zip(columns.pressed)
+            .zip(columns.released)
+            .zip(columns.moves)
+            .map(|(((ms, pressed), released), moves)| RecordEntry {
+                ms,
+                pressed,
+                released,
+                moves,
+                wait: wait.next().flatten(),
+                repeated: repeated.next().unwrap_or_default(),
+            })
+            .collect())
+    }
+}
+
+/// Records saved as entries or as `ColumnarRecords`.
+fn deserialize_records<'de, D>(deserializer: D) -> Result<Vec<RecordEntry>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    // through Value rather than an untagged enum, which cannot hold the tags of enums
+    let value = serde_yml::Value::deserialize(deserializer)?;
+    if value.is_mapping() {
+        let columns: ColumnarRecords = serde_yml::from_value(value).map_err(D::Error::custom)?;
+        columns.try_into().map_err(D::Error::custom)
+    } else {
+        serde_yml::from_value(value).map_err(D::Error::custom)
+    }
+}
+
+#[allow(unused)]
+impl RecordEntry {
+    /// An entry without input pausing the playback for wait_ms.
+    pub fn wait(ms: f64, wait_ms: f64) -> Self {
+        Self {
+            ms,
+            pressed: vec![],
+            released: vec![],
+            moves: vec![],
+            wait: Some(wait_ms),
+            repeated: vec![],
+        }
+    }
+}
+
+/// Named records kept in the recorder file.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Clip {
+    pub name: String,
+    pub records: Vec<RecordEntry>,
+    /// unix time in ms
+    pub created: f64,
+    /// unix time in ms of the last save or load
+    pub accessed: f64,
+    /// CRC-32 of the name and records, not checked if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u32>,
+}
+
+impl Clip {
+    fn checksum(name: &str, records: &[RecordEntry]) -> u32 {
+        let records = serde_yml::to_string(records).unwrap_or_default();
+        crc32(name.bytes().chain([0]).chain(records.bytes()))
+    }
+    fn is_intact(&self) -> bool {
+        self.checksum
+            .is_none_or(|sum| sum == Self::checksum(&self.name, &self.records))
+    }
+}
+
+/// CRC-32 (IEEE) of the bytes.
+fn crc32(bytes: impl IntoIterator<Item = u8>) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Directory of the config files, the current directory if not set.
+const CONFIG_DIR_VAR: &str = "GMC_CONFIG_DIR";
+
+/// The path resolved against `GMC_CONFIG_DIR` if it is relative.
+fn config_path(path: &str) -> String {
+    config_path_in(std::env::var_os(CONFIG_DIR_VAR).as_deref(), path)
+}
+
+/// The path resolved against `dir` if it is relative.
+fn config_path_in(dir: Option<&std::ffi::OsStr>, path: &str) -> String {
+    match dir {
+        Some(dir) if !dir.is_empty() && std::path::Path::new(path).is_relative() => {
+            std::path::Path::new(dir)
+                .join(path)
+                .to_string_lossy()
+                .into_owned()
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Update the held keys by the presses and releases of the records.
+fn hold_keys(held: &mut Vec<AnyKey>, records: &[RecordEntry]) {
+    for record in records {
+        for key in &record.pressed {
+            if !held.contains(key) {
+                held.push(key.clone());
+            }
+        }
+        held.retain(|k| !record.released.contains(k));
+    }
+}
+
+fn unix_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
 }
 
 #[test]
@@ -479,7 +2660,7 @@ fn test_yaml() {
 #[test]
 fn test_recorder() {
     let mut record = Recorder::from_file("config.yaml".to_string());
-    record.init();
+    record.init().unwrap();
     loop {
         record.listen();
     }
@@ -532,7 +2713,7 @@ fn test_player() {
 
     let mut record = Recorder::from_file("config.yaml".to_string());
     println!("records length: {}", record.records.len());
-    record.player.init();
+    record.player.init().unwrap();
     record.state = RecorderState::Ready;
     record
         .recorder
@@ -548,3 +2729,1580 @@ fn test_player() {
     std::thread::sleep(std::time::Duration::from_secs(2));
     record.match_shortcuts();
 }
+
+#[test]
+fn test_export_python() {
+    let entry = |ms, pressed: Vec<AnyKey>, released: Vec<AnyKey>, moves| RecordEntry {
+        ms,
+        pressed,
+        released,
+        moves,
+        ..Default::default()
+    };
+    let record = Recorder {
+        records: vec![
+            entry(
+                100.0,
+                vec![rdev::Key::ShiftLeft.into(), rdev::Key::KeyA.into()],
+                vec![],
+                vec![AnyOffset::Mouse(10.0, 20.0)],
+            ),
+            entry(
+                150.0,
+                vec![AnyKey::MouseButton(0), AnyKey::Controller(0, 0x1000)],
+                vec![rdev::Key::KeyA.into()],
+                vec![AnyOffset::LeftStick(0, 0.5, 0.0)],
+            ),
+            RecordEntry {
+                wait: Some(500.0),
+                ..entry(
+                    200.0,
+                    vec![rdev::Key::Quote.into()],
+                    vec![rdev::Key::ShiftLeft.into(), AnyKey::MouseButton(0)],
+                    vec![AnyOffset::Wheel(0.0, -1.0)],
+                )
+            },
+            entry(250.0, vec![], vec![rdev::Key::Quote.into()], vec![]),
+        ],
+        ..Default::default()
+    };
+    let script = record.export_python();
+    assert!(script.starts_with(PYTHON_HEADER));
+    let lines: Vec<_> = script[PYTHON_HEADER.len()..].lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            "keyboard.press(Key.shift_l)",
+            "keyboard.press('a')",
+            "mouse.position = (10, 20)",
+            "time.sleep(0.050)",
+            "mouse.press(Button.left)",
+            "# controller 0 press 0x1000",
+            "keyboard.release('a')",
+            "# controller LeftStick(0, 0.5, 0.0)",
+            "time.sleep(0.050)",
+            "keyboard.press('\\'')",
+            "keyboard.release(Key.shift_l)",
+            "mouse.release(Button.left)",
+            "mouse.scroll(0, -1)",
+            // the explicit wait is added to the gap
+            "time.sleep(0.550)",
+            "keyboard.release('\\'')",
+        ]
+    );
+}
+
+#[test]
+fn test_export_sdl_mapping() {
+    let record = Recorder {
+        records: vec![
+            RecordEntry {
+                ms: 0.0,
+                pressed: vec![AnyKey::Controller(0, 0x1000), AnyKey::Controller(0, 0x2000)],
+                moves: vec![AnyOffset::LeftStick(0, 0.5, 0.0)],
+                ..Default::default()
+            },
+            RecordEntry {
+                ms: 10.0,
+                pressed: vec![AnyKey::Controller(0, 0x4000), AnyKey::Controller(0, 0x8000)],
+                released: vec![AnyKey::Controller(0, 0x1000)],
+                moves: vec![AnyOffset::RightStick(0, 0.0, -1.0)],
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    let mapping = record.export_sdl_mapping();
+    assert!(mapping.starts_with("78696e70757401000000000000000000,XInput Controller,"));
+    for entry in [
+        "a:b0,",
+        "b:b1,",
+        "x:b2,",
+        "y:b3,",
+        "leftx:a0,",
+        "lefty:a1,",
+        "rightx:a3,",
+        "righty:a4,",
+    ] {
+        assert!(mapping.contains(entry), "{entry} not in {mapping}");
+    }
+    assert!(!mapping.contains("lefttrigger"));
+    assert!(!mapping.contains("start"));
+    assert!(mapping.ends_with("platform:Windows,"));
+}
+
+#[test]
+fn test_run_script_with_assertions() {
+    let entry = |ms| RecordEntry {
+        ms,
+        ..Default::default()
+    };
+    let script = AssertionScript {
+        records: vec![
+            RecordEntry {
+                pressed: vec![rdev::Key::KeyA.into()],
+                ..entry(0.0)
+            },
+            entry(10.0),
+            RecordEntry {
+                moves: vec![AnyOffset::LeftStick(0, 1.0, 0.0)],
+                ..entry(20.0)
+            },
+            RecordEntry {
+                released: vec![rdev::Key::KeyA.into()],
+                ..entry(30.0)
+            },
+        ],
+        tags: vec![
+            (15.0, "first".to_string()),
+            (30.0, "second".to_string()),
+            (40.0, "third".to_string()),
+        ],
+    };
+    let mut record = Recorder::default();
+    let engine = EngineSink::default();
+    record.player.init_without_pad().unwrap();
+    record.player.set_engine_sink(Some(engine.clone()));
+    let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+    let counter = calls.clone();
+    let report = record.run_script_with_assertions(
+        &script,
+        Box::new(move || {
+            counter.set(counter.get() + 1);
+            counter.get() != 2
+        }),
+    );
+    assert_eq!(calls.get(), 3);
+    assert_eq!(
+        report.checkpoints,
+        vec![
+            ("first".to_string(), true),
+            ("second".to_string(), false),
+            ("third".to_string(), true),
+        ]
+    );
+    assert!(!report.passed());
+    // played once, the key held across the first checkpoint is released once at 30ms
+    use crate::player::RecordedEvent;
+    let events: Vec<_> = engine.0.lock().unwrap().drain(..).collect();
+    let key = AnyKey::from(rdev::Key::KeyA);
+    assert_eq!(
+        events.iter().map(|e| e.1.clone()).collect::<Vec<_>>(),
+        vec![
+            RecordedEvent::Press(key.clone()),
+            RecordedEvent::Move(AnyOffset::LeftStick(0, 1.0, 0.0)),
+            RecordedEvent::Release(key),
+        ]
+    );
+    assert!(events[2].0 >= 30.0);
+
+    // not started, nothing is played
+    let mut record = Recorder::default();
+    let report = record.run_script_with_assertions(&script, Box::new(|| true));
+    assert!(report.checkpoints.iter().all(|(_, pass)| !pass));
+}
+
+#[test]
+fn test_append_gap() {
+    let entry = |ms| RecordEntry {
+        ms,
+        ..Default::default()
+    };
+    let mut record = Recorder {
+        records: vec![entry(0.0), entry(10.0)],
+        ..Default::default()
+    };
+    record.config.append_gap_ms = 100.0;
+    record.recorder.time_ms = 1000.0;
+    record.append_record();
+    record.next_ms(1005.0);
+    assert_eq!(record.state, RecorderState::Recording);
+    assert_eq!(record.records.len(), 3);
+    assert_eq!(record.records[2].ms, 115.0);
+}
+
+#[test]
+fn test_seek_forward() {
+    let records: Vec<_> = (0..=10)
+        .map(|i| RecordEntry {
+            ms: i as f64 * 100.0,
+            ..Default::default()
+        })
+        .collect();
+    let mut record = Recorder {
+        records,
+        ..Default::default()
+    };
+    record.config.seek_step_ms = 500.0;
+    record.player.init_without_pad().unwrap();
+    record.state = RecorderState::Ready;
+    record.start_playback();
+    assert!(player::wait_until(1000, || record.player.get_progress() >= 1));
+    let pos = record.player.get_progress();
+
+    record.recorder.pressed_keys.push(rdev::Key::Alt.into());
+    record
+        .recorder
+        .pressed_keys
+        .push(rdev::Key::RightArrow.into());
+    record.match_shortcuts();
+    // held shortcut seeks only once
+    record.match_shortcuts();
+    // 5 entries ahead, sooner than played at their pace
+    assert!(player::wait_until(300, || record.player.get_progress() >= pos + 5));
+    assert!(record.player.get_progress() < pos + 8);
+    assert_eq!(record.state, RecorderState::Playing);
+}
+
+#[test]
+fn test_watch_foreground() {
+    #[derive(Debug)]
+    struct MockForeground(std::rc::Rc<std::cell::RefCell<String>>);
+    impl ForegroundSource for MockForeground {
+        fn process_name(&self) -> Option<String> {
+            Some(self.0.borrow().clone())
+        }
+    }
+    let dir = std::env::temp_dir();
+    let path_a = dir
+        .join("gmc_test_game_a.yaml")
+        .to_string_lossy()
+        .to_string();
+    let path_b = dir
+        .join("gmc_test_game_b.yaml")
+        .to_string_lossy()
+        .to_string();
+    let config_a = Config {
+        interval: 1.0,
+        ..Config::new()
+    };
+    let config_b = Config {
+        interval: 2.0,
+        ..Config::new()
+    };
+    std::fs::write(&path_a, serde_yml::to_string(&config_a).unwrap()).unwrap();
+    std::fs::write(&path_b, serde_yml::to_string(&config_b).unwrap()).unwrap();
+
+    let name = std::rc::Rc::new(std::cell::RefCell::new("Game_A.exe".to_string()));
+    let mut record = Recorder {
+        foreground: Some(Box::new(MockForeground(name.clone()))),
+        state: RecorderState::Ready,
+        ..Default::default()
+    };
+    record.config.game_configs = HashMap::from([
+        ("game_a.exe".to_string(), path_a.clone()),
+        ("game_b.exe".to_string(), path_b.clone()),
+    ]);
+    record.watch_foreground();
+    assert_eq!(record.config.interval, 1.0);
+    assert_eq!(record.config.game_configs.len(), 2);
+
+    // not swapped while recording
+    *name.borrow_mut() = "game_b.exe".to_string();
+    record.now_ms = 1000.0;
+    record.state = RecorderState::Recording;
+    record.watch_foreground();
+    assert_eq!(record.config.interval, 1.0);
+
+    record.state = RecorderState::Ready;
+    record.watch_foreground();
+    assert_eq!(record.config.interval, 2.0);
+
+    // unknown process keeps the current config
+    *name.borrow_mut() = "explorer.exe".to_string();
+    record.now_ms = 2000.0;
+    record.watch_foreground();
+    assert_eq!(record.config.interval, 2.0);
+
+    // checked at most every 500ms
+    *name.borrow_mut() = "game_a.exe".to_string();
+    record.now_ms = 2200.0;
+    record.watch_foreground();
+    assert_eq!(record.config.interval, 2.0);
+    record.now_ms = 2500.0;
+    record.watch_foreground();
+    assert_eq!(record.config.interval, 1.0);
+
+    let _ = std::fs::remove_file(path_a);
+    let _ = std::fs::remove_file(path_b);
+}
+
+#[test]
+fn test_no_coalesce() {
+    let record_keys = |no_coalesce| {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut record = Recorder {
+            recv: Some(rx),
+            ..Default::default()
+        };
+        record.config.no_coalesce = no_coalesce;
+        record.start_record(0);
+        for (i, key) in [rdev::Key::KeyA, rdev::Key::KeyB, rdev::Key::KeyC]
+            .into_iter()
+            .enumerate()
+        {
+            let ev = rdev::EventType::KeyPress(key);
+            tx.send(CallbackType::MK(i as f64 * 0.3, ev, String::new()))
+                .unwrap();
+            record.listen();
+        }
+        // flush the last entry
+        let ev = rdev::EventType::MouseMove { x: 0.0, y: 0.0 };
+        tx.send(CallbackType::MK(10.0, ev, String::new())).unwrap();
+        record.listen();
+        record
+            .records
+            .into_iter()
+            .filter(|r| !r.pressed.is_empty())
+            .map(|r| (r.ms, r.pressed))
+            .collect::<Vec<_>>()
+    };
+    let a: AnyKey = rdev::Key::KeyA.into();
+    let b: AnyKey = rdev::Key::KeyB.into();
+    let c: AnyKey = rdev::Key::KeyC.into();
+    assert_eq!(
+        record_keys(false),
+        vec![(10.0, vec![a.clone(), b.clone(), c.clone()])]
+    );
+    let res = record_keys(true);
+    assert_eq!(res.len(), 3);
+    assert_eq!(res[0].1, vec![a]);
+    assert_eq!(res[1].1, vec![b]);
+    assert_eq!(res[2].1, vec![c]);
+    assert!(res[0].0 < res[1].0 && res[1].0 < res[2].0);
+}
+
+#[test]
+fn test_cancel_recording() {
+    let entry = |ms| RecordEntry {
+        ms,
+        pressed: vec![rdev::Key::KeyA.into()],
+        ..Default::default()
+    };
+    let before = vec![entry(0.0), entry(10.0)];
+    let mut record = Recorder {
+        records: before.clone(),
+        state: RecorderState::Ready,
+        ..Default::default()
+    };
+    for continue_at in [0, 1] {
+        record.start_record(continue_at);
+        record.recorder.key_down(rdev::Key::KeyB.into());
+        record.next_ms(5.0);
+        record.recorder.key_up(rdev::Key::KeyB.into());
+        record.next_ms(15.0);
+        assert_eq!(record.records.len(), 4);
+        record.cancel_recording();
+        assert_eq!(record.state, RecorderState::Ready);
+        assert_eq!(record.records, before);
+    }
+    // no-op when not recording
+    record.cancel_recording();
+    assert_eq!(record.records, before);
+}
+
+#[test]
+fn test_suppress_windows_key() {
+    let entry = |ms, pressed: Vec<rdev::Key>, released: Vec<rdev::Key>| RecordEntry {
+        ms,
+        pressed: pressed.into_iter().map(AnyKey::from).collect(),
+        released: released.into_iter().map(AnyKey::from).collect(),
+        ..Default::default()
+    };
+    use rdev::Key::{KeyD, MetaLeft};
+    let mut record = Recorder {
+        records: vec![
+            entry(0.0, vec![MetaLeft], vec![]),
+            entry(10.0, vec![], vec![MetaLeft]),
+            entry(20.0, vec![MetaLeft], vec![]),
+            entry(30.0, vec![KeyD], vec![]),
+            entry(40.0, vec![], vec![KeyD, MetaLeft]),
+        ],
+        ..Default::default()
+    };
+    assert_eq!(record.playback_records(), record.records);
+    record.config.suppress_windows_key = true;
+    let res = record.playback_records();
+    assert_eq!(res.len(), record.records.len());
+    assert_eq!(res[0], entry(0.0, vec![], vec![]));
+    assert_eq!(res[1], entry(10.0, vec![], vec![]));
+    assert_eq!(res[2..], record.records[2..]);
+}
+
+#[test]
+fn test_mirror_horizontal() {
+    let record = |key: rdev::Key, moves| RecordEntry {
+        ms: 0.0,
+        pressed: vec![key.into(), AnyKey::Controller(0, 0x0008)],
+        moves,
+        repeated: vec![key.into()],
+        ..Default::default()
+    };
+    let mut recorder = Recorder {
+        records: vec![record(
+            rdev::Key::KeyD,
+            vec![
+                AnyOffset::LeftStick(0, 1.0, 0.5),
+                AnyOffset::RightStick(0, -1.5, 0.0),
+                AnyOffset::MouseDelta(12.0, 3.0),
+                AnyOffset::Mouse(100.0, 50.0),
+            ],
+        )],
+        ..Default::default()
+    };
+    recorder.config.mirror_horizontal = true;
+    let mut mirrored = record(
+        rdev::Key::KeyA,
+        vec![
+            AnyOffset::LeftStick(0, -1.0, 0.5),
+            AnyOffset::RightStick(0, 1.0, 0.0),
+            AnyOffset::MouseDelta(-12.0, 3.0),
+            AnyOffset::Mouse(100.0, 50.0),
+        ],
+    );
+    mirrored.pressed[1] = AnyKey::Controller(0, 0x0004);
+    assert_eq!(recorder.playback_records(), vec![mirrored]);
+    // keys out of the pairs are kept
+    recorder.config.mirror_keys.clear();
+    recorder.records[0].moves.clear();
+    assert_eq!(
+        recorder.playback_records()[0].pressed,
+        recorder.records[0].pressed
+    );
+}
+
+#[test]
+fn test_bookmarks() {
+    let records: Vec<_> = (0..=10)
+        .map(|i| RecordEntry {
+            ms: i as f64 * 100.0,
+            ..Default::default()
+        })
+        .collect();
+    let mut record = Recorder {
+        records,
+        ..Default::default()
+    };
+    record.add_bookmark("boss".to_string(), 750.0);
+    record.add_bookmark("start".to_string(), 150.0);
+    record.config.jump_to_bookmark = BTreeMap::from([(
+        "boss".to_string(),
+        ShortCuts::Contains(vec![ShortCut::alt(rdev::Key::KeyB)]),
+    )]);
+    let path = std::env::temp_dir().join("gmc_test_bookmarks.yaml");
+    let path = path.to_string_lossy().to_string();
+    record.save_to_file(path.clone());
+    let mut record = Recorder::from_file(path.clone());
+    let _ = std::fs::remove_file(path);
+    assert_eq!(record.bookmarks.len(), 2);
+    assert_eq!(record.bookmarks["boss"], 750.0);
+
+    record.player.init_without_pad().unwrap();
+    record.state = RecorderState::Ready;
+    record.start_playback();
+    assert!(record.jump_to_bookmark("start"));
+    assert!(!record.jump_to_bookmark("missing"));
+    // the entry at 200ms is next, the ones after come at their pace
+    assert!(player::wait_until(1000, || record.player.get_progress() >= 2));
+    assert!(record.player.get_progress() < 4);
+
+    record.recorder.pressed_keys.push(rdev::Key::Alt.into());
+    record.recorder.pressed_keys.push(rdev::Key::KeyB.into());
+    record.match_shortcuts();
+    // sooner than played at their pace
+    assert!(player::wait_until(300, || record.player.get_progress() >= 8));
+    assert!(record.player.get_progress() < 10);
+}
+
+#[test]
+fn test_pressed_keys() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut record = Recorder {
+        recv: Some(rx),
+        ..Default::default()
+    };
+    let events = [
+        rdev::EventType::KeyPress(rdev::Key::KeyW),
+        rdev::EventType::ButtonPress(rdev::Button::Left),
+        rdev::EventType::KeyPress(rdev::Key::ShiftLeft),
+        rdev::EventType::KeyRelease(rdev::Key::KeyW),
+        rdev::EventType::MouseMove { x: 10.0, y: 20.0 },
+    ];
+    for (i, ev) in events.into_iter().enumerate() {
+        tx.send(CallbackType::MK(i as f64 * 10.0, ev, String::new()))
+            .unwrap();
+        record.listen();
+    }
+    tx.send(CallbackType::Ctrl(
+        60.0,
+        1,
+        ControllerEvent::LSticksMove(0.5, -0.5),
+    ))
+    .unwrap();
+    record.listen();
+    assert_eq!(
+        record.pressed_keys(),
+        &[AnyKey::MouseButton(0), rdev::Key::ShiftLeft.into()]
+    );
+    assert_eq!(record.current_offsets().mouse, (10.0, 20.0));
+    assert_eq!(record.current_offsets().left_stick[1], (0.5, -0.5));
+}
+
+#[test]
+fn test_trigger_source() {
+    #[derive(Debug)]
+    struct MockTrigger(Vec<TriggerSignal>);
+    impl TriggerSource for MockTrigger {
+        fn poll(&mut self) -> Option<TriggerSignal> {
+            self.0.pop()
+        }
+    }
+    let mut record = Recorder {
+        state: RecorderState::Ready,
+        ..Default::default()
+    };
+    record.add_trigger(Box::new(MockTrigger(vec![TriggerSignal::Start])));
+    assert_eq!(record.match_triggers(), RecorderState::Recording);
+    // nothing pending
+    assert_eq!(record.match_triggers(), RecorderState::Recording);
+    record.add_trigger(Box::new(MockTrigger(vec![TriggerSignal::Stop])));
+    assert_eq!(record.match_triggers(), RecorderState::Ready);
+}
+
+#[test]
+fn test_max_clips() {
+    let entry = |ms| RecordEntry {
+        ms,
+        ..Default::default()
+    };
+    let mut record = Recorder::default();
+    record.config.max_clips = Some(2);
+    for (i, name) in ["a", "b", "c"].into_iter().enumerate() {
+        record.records = vec![entry(i as f64)];
+        record.save_clip(name.to_string());
+    }
+    assert_eq!(record.clip_names(), vec!["b", "c"]);
+    assert!(!record.load_clip("a"));
+
+    // loading b makes c the least recently used
+    assert!(record.load_clip("b"));
+    assert_eq!(record.records, vec![entry(1.0)]);
+    record.save_clip("d".to_string());
+    assert_eq!(record.clip_names(), vec!["b", "d"]);
+    assert!(record.clips[0].accessed >= record.clips[0].created);
+}
+
+#[test]
+fn test_save_and_new() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let records = vec![RecordEntry {
+        ms: 10.0,
+        pressed: vec![AnyKey::from(rdev::Key::KeyW)],
+        ..Default::default()
+    }];
+    let mut record = Recorder {
+        recv: Some(rx),
+        state: RecorderState::Ready,
+        records: records.clone(),
+        ..Default::default()
+    };
+    record.config.save_and_new = ShortCuts::Contains(vec![ShortCut::alt(rdev::Key::Num3)]);
+    let dir = std::env::temp_dir().join("gmc_test_save_and_new");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    record.config.takes_dir = Some(dir.to_string_lossy().to_string());
+    let saved = || {
+        std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| Some(e.ok()?.path()))
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                name.starts_with("records-") && name.ends_with(".yaml")
+            })
+            .map(|path| path.to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert!(saved().is_empty());
+    let mut send = |ev| {
+        tx.send(CallbackType::MK(0.0, ev, String::new())).unwrap();
+        record.listen();
+        record.match_shortcuts()
+    };
+    send(rdev::EventType::KeyPress(rdev::Key::Alt));
+    let state = send(rdev::EventType::KeyPress(rdev::Key::Num3));
+    let files = saved();
+    let loaded: Vec<_> = files
+        .iter()
+        .map(|file| Recorder::load_take(file).unwrap())
+        .collect();
+    for file in &files {
+        let _ = std::fs::remove_file(file);
+    }
+    assert_eq!(loaded, vec![records]);
+    assert_eq!(state, RecorderState::Recording);
+    assert!(record.records[record.rec_pos.1..].is_empty());
+
+    // only from ready, not while recording
+    assert_eq!(record.match_shortcuts(), RecorderState::Recording);
+    assert!(saved().is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_save_take_records_file() {
+    let dir = std::env::temp_dir();
+    let file = dir.join("test_save_take_records_file.bin");
+    let take = |i: usize| dir.join(format!("test_save_take_records_file-{i}.yaml"));
+    let entry = |ms| RecordEntry {
+        ms,
+        pressed: vec![AnyKey::from(rdev::Key::KeyW)],
+        ..Default::default()
+    };
+    let mut record = Recorder::default();
+    record.config.records_file = Some(file.to_string_lossy().to_string());
+    record.config.columnar = true;
+    // every take keeps its own records, the records file is not touched
+    let takes = [vec![entry(10.0)], vec![entry(20.0), entry(30.0)]];
+    for (i, records) in takes.iter().enumerate() {
+        record.records = records.clone();
+        record.save_take(&take(i).to_string_lossy()).unwrap();
+    }
+    let loaded: Vec<_> = (0..2)
+        .map(|i| Recorder::load_take(&take(i).to_string_lossy()))
+        .collect();
+    let text = std::fs::read_to_string(take(0)).unwrap_or_default();
+    for i in 0..2 {
+        let _ = std::fs::remove_file(take(i));
+    }
+    assert!(!file.exists());
+    assert_eq!(loaded, takes.map(Ok));
+    assert!(!text.contains("config"), "{text}");
+}
+
+#[test]
+fn test_clip_checksum() {
+    let path = std::env::temp_dir().join("gmc_test_clip_checksum.yaml");
+    let path = path.to_str().unwrap();
+    let entry = |ms| RecordEntry {
+        ms,
+        pressed: vec![AnyKey::from(rdev::Key::KeyA)],
+        ..Default::default()
+    };
+    let mut record = Recorder::default();
+    for (name, ms) in [("a", 1111.0), ("b", 2222.0), ("c", 3333.0)] {
+        record.records = vec![entry(ms)];
+        record.save_clip(name.to_string());
+    }
+    record.save_to_file(path.to_string());
+    let loaded = Recorder::from_file(path.to_string());
+    assert_eq!(loaded.clip_names(), vec!["a", "b", "c"]);
+    assert!(loaded.corrupt_clips().is_empty());
+
+    // a is changed, b cannot be parsed
+    let s = std::fs::read_to_string(path).unwrap();
+    let s = s.replace("1111.0", "1112.0").replace("2222.0", "oops");
+    std::fs::write(path, s).unwrap();
+    let mut loaded = Recorder::from_file(path.to_string());
+    let _ = std::fs::remove_file(path);
+    assert_eq!(loaded.clip_names(), vec!["c"]);
+    assert_eq!(loaded.corrupt_clips(), ["a", "b"]);
+    assert!(loaded.load_clip("c"));
+    assert_eq!(loaded.records, vec![entry(3333.0)]);
+}
+
+#[test]
+fn test_invert() {
+    let entry = |ms, pressed: &[rdev::Key], released: &[rdev::Key], moves| RecordEntry {
+        ms,
+        pressed: pressed.iter().map(|&k| AnyKey::from(k)).collect(),
+        released: released.iter().map(|&k| AnyKey::from(k)).collect(),
+        moves,
+        ..Default::default()
+    };
+    use rdev::Key::{KeyA, KeyB};
+    let records = vec![
+        entry(0.0, &[], &[], vec![AnyOffset::Mouse(10.0, 20.0)]),
+        entry(
+            10.0,
+            &[KeyA],
+            &[],
+            vec![AnyOffset::LeftStick(0, 0.5, -0.25)],
+        ),
+        entry(20.0, &[KeyB], &[KeyA], vec![]),
+        entry(30.0, &[KeyA], &[], vec![AnyOffset::Wheel(0.0, 1.0)]),
+        entry(40.0, &[], &[KeyA, KeyB], vec![]),
+        entry(50.0, &[], &[], vec![]),
+    ];
+    let mut record = Recorder {
+        records: records.clone(),
+        ..Default::default()
+    };
+    record.invert();
+    let inverted = vec![
+        entry(0.0, &[KeyA, KeyB], &[], vec![AnyOffset::Mouse(10.0, 20.0)]),
+        entry(
+            10.0,
+            &[],
+            &[KeyA],
+            vec![AnyOffset::LeftStick(0, -0.5, 0.25)],
+        ),
+        entry(20.0, &[KeyA], &[KeyB], vec![]),
+        entry(30.0, &[], &[KeyA], vec![AnyOffset::Wheel(-0.0, -1.0)]),
+        entry(40.0, &[KeyA, KeyB], &[], vec![]),
+        entry(50.0, &[], &[KeyA, KeyB], vec![]),
+    ];
+    assert_eq!(record.records, inverted);
+    record.invert();
+    assert_eq!(record.records, records);
+}
+
+#[test]
+fn test_invert_tap() {
+    let entry = |ms, pressed: &[rdev::Key], released: &[rdev::Key]| RecordEntry {
+        ms,
+        pressed: pressed.iter().map(|&k| AnyKey::from(k)).collect(),
+        released: released.iter().map(|&k| AnyKey::from(k)).collect(),
+        ..Default::default()
+    };
+    use rdev::Key::KeyA;
+    let records = vec![
+        entry(0.0, &[], &[]),
+        entry(10.0, &[KeyA], &[KeyA]),
+        entry(20.0, &[], &[]),
+    ];
+    let mut record = Recorder {
+        records: records.clone(),
+        ..Default::default()
+    };
+    record.invert();
+    let inverted = vec![
+        entry(0.0, &[KeyA], &[]),
+        entry(10.0, &[], &[KeyA]),
+        entry(10.0, &[KeyA], &[]),
+        entry(20.0, &[], &[KeyA]),
+    ];
+    assert_eq!(record.records, inverted);
+    record.invert();
+    assert_eq!(record.records, records);
+}
+
+#[test]
+fn test_config_save_load() {
+    let path = std::env::temp_dir().join("gmc_test_config_save_load.yaml");
+    let path = path.to_str().unwrap();
+    let mut config = Config::new();
+    config.start_record = ShortCuts::Contains(vec![ShortCut::alt(rdev::Key::KeyR)]);
+    config.seek_step_ms = 250.0;
+    config.save(path).unwrap();
+    let s = std::fs::read_to_string(path).unwrap();
+    assert!(!s.lines().any(|l| l.starts_with("records:")));
+
+    let entry = RecordEntry {
+        ms: 10.0,
+        pressed: vec![AnyKey::from(rdev::Key::KeyA)],
+        ..Default::default()
+    };
+    let mut record = Recorder {
+        records: vec![entry.clone()],
+        ..Default::default()
+    };
+    record.apply_config(Config::load(path).unwrap());
+    let _ = std::fs::remove_file(path);
+    assert_eq!(record.config, config);
+    assert_eq!(record.records, vec![entry]);
+    assert!(Config::load(path).is_err());
+}
+
+#[test]
+fn test_config_diff() {
+    let ours = Config::new();
+    let mut theirs = Config::new();
+    assert_eq!(ours.diff(&theirs), vec![]);
+    theirs.toggle_loop = ShortCuts::Contains(vec![ShortCut::alt(rdev::Key::KeyK)]);
+    assert_eq!(
+        ours.diff(&theirs),
+        vec![
+            ConfigDiff::Removed {
+                action: "toggle_loop".to_string(),
+                shortcut: format!("{:?}", ShortCut::alt(rdev::Key::KeyL)),
+            },
+            ConfigDiff::Added {
+                action: "toggle_loop".to_string(),
+                shortcut: format!("{:?}", ShortCut::alt(rdev::Key::KeyK)),
+            },
+        ]
+    );
+    let mut theirs = Config::new();
+    theirs.seek_step_ms = 250.0;
+    theirs.jump_to_bookmark = BTreeMap::from([("boss".to_string(), ShortCuts::Exclude(vec![]))]);
+    assert_eq!(
+        ours.diff(&theirs),
+        vec![
+            ConfigDiff::Inverted {
+                action: "jump_to_bookmark.boss".to_string()
+            },
+            ConfigDiff::Changed {
+                name: "seek_step_ms".to_string(),
+                ours: "1000.0".to_string(),
+                theirs: "250.0".to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_offset_mouse() {
+    let entry = |ms, moves| RecordEntry {
+        ms,
+        pressed: vec![AnyKey::MouseButton(0)],
+        moves,
+        ..Default::default()
+    };
+    let mut record = Recorder {
+        records: vec![
+            entry(
+                0.0,
+                vec![AnyOffset::Mouse(100.0, 200.0), AnyOffset::Wheel(0.0, 1.0)],
+            ),
+            entry(10.0, vec![AnyOffset::LeftStick(0, 0.5, 0.5)]),
+            entry(20.0, vec![AnyOffset::Mouse(0.0, 0.0)]),
+        ],
+        ..Default::default()
+    };
+    record.offset_mouse(-50.0, 25.0);
+    assert_eq!(
+        record.records,
+        vec![
+            entry(
+                0.0,
+                vec![AnyOffset::Mouse(50.0, 225.0), AnyOffset::Wheel(0.0, 1.0)]
+            ),
+            entry(10.0, vec![AnyOffset::LeftStick(0, 0.5, 0.5)]),
+            entry(20.0, vec![AnyOffset::Mouse(-50.0, 25.0)]),
+        ]
+    );
+}
+
+#[test]
+fn test_auto_pause_idle() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut record = Recorder {
+        recv: Some(rx),
+        ..Default::default()
+    };
+    record.config.auto_pause_idle_ms = Some(1000.0);
+    record.start_record(0);
+    for ms in [0.0, 10.0, 5000.0, 5010.0, 5020.0] {
+        let ev = rdev::EventType::MouseMove { x: ms, y: 0.0 };
+        tx.send(CallbackType::MK(ms, ev, String::new())).unwrap();
+        record.listen();
+    }
+    let ms: Vec<_> = record.records.iter().map(|r| r.ms).collect();
+    assert_eq!(
+        ms,
+        vec![
+            10.0,
+            10.0 + IDLE_GAP_MS,
+            20.0 + IDLE_GAP_MS,
+            30.0 + IDLE_GAP_MS
+        ]
+    );
+    // the events are all kept
+    assert_eq!(record.records[1].moves, vec![AnyOffset::Mouse(10.0, 0.0)]);
+}
+
+#[test]
+fn test_stream_json() {
+    #[derive(Clone, Default)]
+    struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+    impl std::io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut record = Recorder {
+        recv: Some(rx),
+        ..Default::default()
+    };
+    let out = Shared::default();
+    record.stream_json(Box::new(out.clone()));
+    record.start_record(0);
+    let key = rdev::EventType::KeyPress(rdev::Key::KeyA);
+    tx.send(CallbackType::MK(5.0, key, String::new())).unwrap();
+    let mouse = rdev::EventType::MouseMove { x: 10.0, y: 20.0 };
+    tx.send(CallbackType::MK(6.5, mouse, String::new()))
+        .unwrap();
+    let stick = ControllerEvent::LSticksMove(0.5, -0.5);
+    tx.send(CallbackType::Ctrl(8.0, 1, stick)).unwrap();
+    for _ in 0..3 {
+        record.listen();
+    }
+    let s = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<serde_json::Value> = s
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["source"], "input");
+    assert_eq!(lines[0]["ms"], 5.0);
+    assert_eq!(lines[0]["event"]["KeyPress"], "KeyA");
+    assert_eq!(lines[1]["event"]["MouseMove"]["y"], 20.0);
+    assert_eq!(lines[2]["source"], "controller");
+    assert_eq!(lines[2]["id"], 1);
+    assert_eq!(lines[2]["event"]["LSticksMove"][1], -0.5);
+    // still recorded
+    assert_eq!(record.pressed_keys(), [AnyKey::from(rdev::Key::KeyA)]);
+}
+
+#[test]
+fn test_camera_mode() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut record = Recorder {
+        recv: Some(rx),
+        ..Default::default()
+    };
+    record.config.camera_mode = true;
+    record.config.screen_scale = 2.0;
+    record.start_record(0);
+    let path = [
+        (960.0, 540.0),
+        (970.0, 540.0),
+        (985.0, 530.0),
+        (985.0, 500.0),
+        (950.0, 520.0),
+    ];
+    for (i, &(x, y)) in path.iter().enumerate() {
+        let ev = rdev::EventType::MouseMove { x, y };
+        tx.send(CallbackType::MK(i as f64 * 10.0, ev, String::new()))
+            .unwrap();
+        record.listen();
+    }
+    record.next_ms(100.0);
+    let deltas: Vec<_> = record
+        .records
+        .iter()
+        .flat_map(|r| &r.moves)
+        .map(|m| match *m {
+            AnyOffset::MouseDelta(dx, dy) => (dx, dy),
+            _ => panic!("Absolute move {m:?} in camera mode"),
+        })
+        .collect();
+    assert_eq!(deltas.len(), path.len() - 1);
+    // replaying the deltas from the start retraces the path
+    let mut pos = path[0];
+    for (&(dx, dy), &expected) in deltas.iter().zip(&path[1..]) {
+        pos = (pos.0 + dx, pos.1 + dy);
+        assert_eq!(pos, expected);
+    }
+}
+
+#[test]
+fn test_effort_metrics() {
+    let entry = |ms, pressed, moves| RecordEntry {
+        ms,
+        pressed,
+        moves,
+        ..Default::default()
+    };
+    let record = Recorder {
+        records: vec![
+            entry(
+                0.0,
+                vec![AnyKey::from(rdev::Key::KeyA), AnyKey::MouseButton(0)],
+                vec![AnyOffset::LeftStick(0, 0.6, 0.8)],
+            ),
+            entry(
+                15000.0,
+                vec![AnyKey::Controller(0, 0x1000)],
+                vec![
+                    AnyOffset::LeftStick(0, 0.0, 0.0),
+                    AnyOffset::RightStick(0, -0.5, 0.0),
+                ],
+            ),
+            entry(30000.0, vec![], vec![AnyOffset::Mouse(500.0, 500.0)]),
+        ],
+        ..Default::default()
+    };
+    let metrics = record.effort_metrics();
+    assert_eq!(metrics.key_presses, 3);
+    assert!((metrics.stick_travel - 2.5).abs() < 1e-9);
+    assert_eq!(metrics.duration_ms, 30000.0);
+    assert!((metrics.actions_per_minute - 6.0).abs() < 1e-9);
+    assert_eq!(
+        Recorder::default().effort_metrics(),
+        EffortMetrics::default()
+    );
+}
+
+#[test]
+fn test_poll_interval() {
+    let config = |interval| Config {
+        interval,
+        ..Default::default()
+    };
+    let min = std::time::Duration::from_millis(1);
+    assert_eq!(config(0.0).poll_interval(), min);
+    assert_eq!(config(0.2).poll_interval(), min);
+    assert_eq!(config(-5.0).poll_interval(), min);
+    assert_eq!(config(f64::NAN).poll_interval(), min);
+    assert_eq!(
+        config(10.0).poll_interval(),
+        std::time::Duration::from_millis(10)
+    );
+}
+
+#[test]
+fn test_high_res_timer() {
+    let interval = std::time::Duration::from_millis(1);
+    let achieved = |high_res| {
+        let start = std::time::Instant::now();
+        let mut timer = PollTimer::new(interval, high_res);
+        for _ in 0..50 {
+            timer.wait();
+        }
+        start.elapsed().as_secs_f64() * 1000.0 / 50.0
+    };
+    let plain = achieved(false);
+    let high_res = achieved(true);
+    // never early, the precision depends on the load of the machine, only bound it loosely
+    assert!(
+        plain >= 1.0 && high_res >= 1.0,
+        "plain: {plain:.3}ms, high res: {high_res:.3}ms for an interval of 1ms"
+    );
+    assert!(high_res < 5.0, "{high_res:.3}ms for an interval of 1ms");
+}
+
+#[test]
+fn test_quantize_gaps() {
+    let entry = |ms| RecordEntry {
+        ms,
+        moves: vec![AnyOffset::Mouse(ms, 0.0)],
+        ..Default::default()
+    };
+    let mut record = Recorder {
+        records: [5.0, 103.0, 148.0, 155.0, 155.0, 410.0]
+            .into_iter()
+            .map(entry)
+            .collect(),
+        ..Default::default()
+    };
+    record.quantize_gaps(50.0);
+    let ms: Vec<_> = record.records.iter().map(|r| r.ms).collect();
+    assert_eq!(ms, vec![5.0, 105.0, 155.0, 205.0, 205.0, 455.0]);
+    // entries keep their order
+    let moves: Vec<_> = record.records.iter().map(|r| r.moves[0].clone()).collect();
+    let expected: Vec<_> = [5.0, 103.0, 148.0, 155.0, 155.0, 410.0]
+        .into_iter()
+        .map(|x| AnyOffset::Mouse(x, 0.0))
+        .collect();
+    assert_eq!(moves, expected);
+}
+
+#[test]
+fn test_clamp_duration() {
+    let entry = |ms, key: rdev::Key| RecordEntry {
+        ms,
+        pressed: vec![AnyKey::from(key)],
+        ..Default::default()
+    };
+    let mut record = Recorder {
+        records: vec![
+            entry(0.0, rdev::Key::KeyA),
+            entry(100.0, rdev::Key::KeyB),
+            entry(60000.0, rdev::Key::KeyC),
+            entry(60100.0, rdev::Key::KeyD),
+        ],
+        ..Default::default()
+    };
+    record.clamp_duration(1000.0);
+    let ms: Vec<_> = record.records.iter().map(|r| r.ms).collect();
+    assert_eq!(ms, vec![0.0, 100.0, 1000.0]);
+    assert_eq!(
+        record.records[2].pressed,
+        vec![AnyKey::from(rdev::Key::KeyC)]
+    );
+    // within the cap nothing changes
+    let records = record.records.clone();
+    record.clamp_duration(1000.0);
+    assert_eq!(record.records, records);
+
+    // waits count towards the duration
+    let mut record = Recorder {
+        records: vec![
+            entry(0.0, rdev::Key::KeyA),
+            RecordEntry::wait(100.0, 500.0),
+            entry(200.0, rdev::Key::KeyB),
+            RecordEntry::wait(300.0, 5000.0),
+            entry(400.0, rdev::Key::KeyC),
+        ],
+        ..Default::default()
+    };
+    record.clamp_duration(1000.0);
+    let waits: Vec<_> = record.records.iter().map(|r| r.wait).collect();
+    assert_eq!(waits, vec![None, Some(500.0), None, Some(200.0), None]);
+    assert_eq!(record.records[4].ms, 300.0);
+}
+
+#[test]
+fn test_trim_delete_range() {
+    let (a, b, c) = (rdev::Key::KeyA, rdev::Key::KeyB, rdev::Key::KeyC);
+    let entry = |ms, pressed: &[rdev::Key], released: &[rdev::Key]| RecordEntry {
+        ms,
+        pressed: pressed.iter().map(|&k| AnyKey::from(k)).collect(),
+        released: released.iter().map(|&k| AnyKey::from(k)).collect(),
+        ..Default::default()
+    };
+    let records = vec![
+        entry(0.0, &[a], &[]),
+        entry(100.0, &[b], &[]),
+        entry(200.0, &[], &[a]),
+        entry(300.0, &[c], &[]),
+        entry(400.0, &[], &[b]),
+        entry(500.0, &[], &[c]),
+    ];
+    let mut record = Recorder {
+        records: records.clone(),
+        ..Default::default()
+    };
+    // A released and C pressed in the cut
+    record.delete_range(150.0, 350.0);
+    let ms: Vec<_> = record.records.iter().map(|r| r.ms).collect();
+    assert_eq!(ms, vec![0.0, 100.0, 150.0, 200.0, 300.0]);
+    assert!(ms.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(record.records[2], entry(150.0, &[c], &[a]));
+    assert_eq!(record.records[4], entry(300.0, &[], &[c]));
+
+    // clamped to the data, nothing held across the cut
+    let mut record = Recorder {
+        records: records.clone(),
+        ..Default::default()
+    };
+    record.delete_range(-1000.0, 50.0);
+    let ms: Vec<_> = record.records.iter().map(|r| r.ms).collect();
+    assert_eq!(ms, vec![0.0, 50.0, 150.0, 250.0, 350.0, 450.0]);
+    assert_eq!(record.records[0], entry(0.0, &[a], &[]));
+    assert_eq!(record.records[1], entry(50.0, &[b], &[]));
+
+    // A is held at the start, C at the end
+    let mut record = Recorder {
+        records: records.clone(),
+        ..Default::default()
+    };
+    record.trim(50.0, 350.0);
+    assert_eq!(
+        record.records,
+        vec![
+            entry(0.0, &[a, b], &[]),
+            entry(100.0, &[], &[a]),
+            entry(200.0, &[c], &[b, c]),
+        ]
+    );
+    record.trim(1000.0, 2000.0);
+    assert!(record.records.is_empty());
+    record.trim(0.0, 100.0);
+    record.delete_range(0.0, 100.0);
+    assert!(record.records.is_empty());
+}
+
+#[test]
+fn test_layer() {
+    let entry = |ms, pressed: &[rdev::Key], released: &[rdev::Key], moves| RecordEntry {
+        ms,
+        pressed: pressed.iter().map(|&k| AnyKey::from(k)).collect(),
+        released: released.iter().map(|&k| AnyKey::from(k)).collect(),
+        moves,
+        ..Default::default()
+    };
+    use rdev::Key::{KeyA, KeyD, ShiftLeft};
+    let left = vec![
+        entry(0.0, &[KeyA, ShiftLeft], &[], vec![]),
+        entry(100.0, &[], &[KeyA], vec![]),
+        entry(300.0, &[], &[ShiftLeft], vec![]),
+    ];
+    let right = vec![
+        entry(50.0, &[], &[], vec![AnyOffset::Mouse(10.0, 10.0)]),
+        entry(
+            100.5,
+            &[KeyD, ShiftLeft],
+            &[],
+            vec![AnyOffset::Mouse(20.0, 10.0)],
+        ),
+        entry(200.0, &[], &[KeyD, ShiftLeft], vec![]),
+    ];
+    let layered = Recorder::layer(&left, &right);
+    assert_eq!(
+        layered,
+        vec![
+            entry(0.0, &[KeyA, ShiftLeft], &[], vec![]),
+            entry(50.0, &[], &[], vec![AnyOffset::Mouse(10.0, 10.0)]),
+            entry(100.0, &[KeyD], &[KeyA], vec![AnyOffset::Mouse(20.0, 10.0)]),
+            // shift is still held by the left hand
+            entry(200.0, &[], &[KeyD], vec![]),
+            entry(300.0, &[], &[ShiftLeft], vec![]),
+        ]
+    );
+}
+
+#[test]
+fn test_shortcut_stable() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut record = Recorder {
+        recv: Some(rx),
+        state: RecorderState::Ready,
+        ..Default::default()
+    };
+    record.config.shortcut_stable_ms = 50.0;
+    let send = |record: &mut Recorder, ms, ev| {
+        tx.send(CallbackType::MK(ms, ev, String::new())).unwrap();
+        record.listen();
+        record.match_shortcuts()
+    };
+    use rdev::EventType::{KeyPress, KeyRelease};
+    // Alt + 1 held only for 20ms
+    send(&mut record, 0.0, KeyPress(rdev::Key::Alt));
+    assert_eq!(
+        send(&mut record, 10.0, KeyPress(rdev::Key::Num1)),
+        RecorderState::Ready
+    );
+    assert_eq!(
+        send(&mut record, 30.0, KeyRelease(rdev::Key::Num1)),
+        RecorderState::Ready
+    );
+    assert_eq!(
+        send(&mut record, 100.0, KeyRelease(rdev::Key::Alt)),
+        RecorderState::Ready
+    );
+    // Alt + 1 held for the stable window
+    send(&mut record, 200.0, KeyPress(rdev::Key::Alt));
+    assert_eq!(
+        send(&mut record, 210.0, KeyPress(rdev::Key::Num1)),
+        RecorderState::Ready
+    );
+    record.now_ms = 240.0;
+    assert_eq!(record.match_shortcuts(), RecorderState::Ready);
+    record.now_ms = 260.0;
+    assert_eq!(record.match_shortcuts(), RecorderState::Recording);
+    send(&mut record, 300.0, KeyRelease(rdev::Key::Num1));
+    send(&mut record, 310.0, KeyRelease(rdev::Key::Alt));
+    send(&mut record, 400.0, KeyPress(rdev::Key::KeyW));
+    send(&mut record, 450.0, KeyRelease(rdev::Key::KeyW));
+    // the mouse moved while waiting for Shift + Alt + 1 to be stable
+    send(&mut record, 500.0, KeyPress(rdev::Key::ShiftLeft));
+    send(&mut record, 510.0, KeyPress(rdev::Key::Alt));
+    send(&mut record, 520.0, KeyPress(rdev::Key::Num1));
+    send(
+        &mut record,
+        530.0,
+        rdev::EventType::MouseMove { x: 5.0, y: 5.0 },
+    );
+    send(
+        &mut record,
+        540.0,
+        rdev::EventType::MouseMove { x: 9.0, y: 9.0 },
+    );
+    assert_eq!(
+        send(
+            &mut record,
+            580.0,
+            rdev::EventType::MouseMove { x: 9.0, y: 9.0 }
+        ),
+        RecorderState::Ready
+    );
+    let w = AnyKey::from(rdev::Key::KeyW);
+    assert!(record.records.iter().any(|r| r.pressed.contains(&w)));
+    assert!(record.records.iter().any(|r| r.released.contains(&w)));
+    let num1 = AnyKey::from(rdev::Key::Num1);
+    assert!(record
+        .records
+        .iter()
+        .all(|r| r.moves.is_empty() && !r.pressed.contains(&num1)));
+}
+
+#[test]
+fn test_columnar() {
+    let records = vec![
+        RecordEntry {
+            ms: 0.0,
+            pressed: vec![AnyKey::from(rdev::Key::KeyA), AnyKey::Controller(0, 0x1000)],
+            moves: vec![AnyOffset::Mouse(1.0, 2.0)],
+            ..Default::default()
+        },
+        RecordEntry::wait(10.0, 500.0),
+        RecordEntry {
+            ms: 20.0,
+            released: vec![AnyKey::from(rdev::Key::KeyA)],
+            moves: vec![
+                AnyOffset::LeftStick(0, 0.5, -0.5),
+                AnyOffset::Wheel(0.0, 1.0),
+            ],
+            ..Default::default()
+        },
+    ];
+    let columns = ColumnarRecords::from(&records[..]);
+    assert_eq!(columns.ms, vec![0.0, 10.0, 20.0]);
+    assert_eq!(Vec::try_from(columns.clone()), Ok(records.clone()));
+    let mut short = columns;
+    short.moves.pop();
+    assert!(Vec::try_from(short).is_err());
+
+    let path = std::env::temp_dir().join("gmc_test_columnar.yaml");
+    let path = path.to_string_lossy().to_string();
+    let mut record = Recorder {
+        records: records.clone(),
+        ..Default::default()
+    };
+    record.config.columnar = true;
+    record.save_to_file(path.clone());
+    let s = std::fs::read_to_string(&path).unwrap();
+    let record = Recorder::from_file(path.clone());
+    let _ = std::fs::remove_file(path);
+    assert!(s.contains("records:\n  ms:"));
+    assert_eq!(record.records, records);
+    assert!(record.config.columnar);
+}
+
+#[test]
+fn test_config_dir() {
+    let dir = std::env::current_dir().unwrap();
+    let absolute = dir.join("game").join("config.yaml");
+    let absolute = absolute.to_str().unwrap();
+    let path = config_path_in(Some(dir.as_os_str()), "config.yaml");
+    assert_eq!(path, dir.join("config.yaml").to_string_lossy());
+    assert_eq!(config_path_in(Some(dir.as_os_str()), absolute), absolute);
+    assert_eq!(
+        config_path_in(Some("".as_ref()), "config.yaml"),
+        "config.yaml"
+    );
+    assert_eq!(config_path_in(None, "config.yaml"), "config.yaml");
+    assert_eq!(config_path_in(None, absolute), absolute);
+}
+
+#[test]
+fn test_records_file() {
+    let records: Vec<_> = (0..3000)
+        .map(|i| RecordEntry {
+            ms: i as f64 * 1.5,
+            pressed: match i % 3 {
+                0 => vec![AnyKey::from(rdev::Key::KeyA), AnyKey::Controller(0, 0x1000)],
+                1 => vec![AnyKey::MouseButton(1)],
+                _ => vec![],
+            },
+            released: match i % 3 {
+                2 => vec![AnyKey::from(rdev::Key::KeyA), AnyKey::MouseButton(1)],
+                _ => vec![AnyKey::Controller(0, 0x1000)],
+            },
+            moves: vec![
+                AnyOffset::Mouse(i as f64, -0.5),
+                AnyOffset::RightTrigger(1, 0.25),
+            ],
+            wait: (i % 100 == 0).then_some(20.0),
+            repeated: match i % 3 {
+                0 => vec![AnyKey::from(rdev::Key::KeyA)],
+                _ => vec![],
+            },
+        })
+        .collect();
+    let dir = std::env::temp_dir();
+    let path = dir.join("gmc_test_records_file.bin");
+    let path = path.to_string_lossy().to_string();
+    let record = Recorder {
+        records: records.clone(),
+        ..Default::default()
+    };
+    record.export_records(path.clone()).unwrap();
+    let mut imported = Recorder::default();
+    imported.import_records(path.clone()).unwrap();
+    assert_eq!(imported.records, records);
+
+    // another version is rejected, the records are kept
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[4..8].copy_from_slice(&2u32.to_le_bytes());
+    std::fs::write(&path, &bytes).unwrap();
+    let err = imported.import_records(path.clone()).unwrap_err();
+    assert!(err.contains("version 2"), "{err}");
+    assert_eq!(imported.records, records);
+    std::fs::write(&path, b"records").unwrap();
+    assert!(imported.import_records(path.clone()).is_err());
+    let _ = std::fs::remove_file(&path);
+
+    // saved apart from the config
+    let config_path = dir.join("gmc_test_records_file.yaml");
+    let config_path = config_path.to_string_lossy().to_string();
+    let mut record = record;
+    record.config.records_file = Some(path.clone());
+    record.save_to_file(config_path.clone());
+    let s = std::fs::read_to_string(&config_path).unwrap();
+    let loaded = Recorder::from_file(config_path.clone());
+    let _ = std::fs::remove_file(&config_path);
+    let _ = std::fs::remove_file(&path);
+    assert!(s.contains("records: []"));
+    assert_eq!(loaded.records, records);
+}
+
+#[test]
+fn test_playback_confirmation() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut record = Recorder {
+        recv: Some(rx),
+        state: RecorderState::Ready,
+        records: [0.0, 10000.0]
+            .into_iter()
+            .map(|ms| RecordEntry {
+                ms,
+                pressed: vec![AnyKey::Controller(0, 0x1000)],
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    record.player.init_without_pad().unwrap();
+    record.config.require_playback_confirmation = true;
+    let mut send = |ms, ev| {
+        tx.send(CallbackType::MK(ms, ev, String::new())).unwrap();
+        record.listen();
+        record.match_shortcuts()
+    };
+    use rdev::EventType::{KeyPress, KeyRelease};
+    // Alt + 2 is gated on the first press, also while it is held
+    send(0.0, KeyPress(rdev::Key::Alt));
+    assert_eq!(send(10.0, KeyPress(rdev::Key::Num2)), RecorderState::Ready);
+    assert_eq!(send(20.0, KeyPress(rdev::Key::Num2)), RecorderState::Ready);
+    assert_eq!(
+        send(30.0, KeyRelease(rdev::Key::Num2)),
+        RecorderState::Ready
+    );
+    // confirmed by pressing again
+    assert_eq!(
+        send(500.0, KeyPress(rdev::Key::Num2)),
+        RecorderState::Playing
+    );
+    send(510.0, KeyRelease(rdev::Key::Num2));
+    record.stop_playback();
+    // no confirmation needed afterwards
+    assert_eq!(record.state, RecorderState::Ready);
+    let mut send = |ms, ev| {
+        tx.send(CallbackType::MK(ms, ev, String::new())).unwrap();
+        record.listen();
+        record.match_shortcuts()
+    };
+    assert_eq!(
+        send(10000.0, KeyPress(rdev::Key::Num2)),
+        RecorderState::Playing
+    );
+    record.stop_playback();
+}
+
+#[test]
+fn test_init_state_dedup() {
+    let path = std::env::temp_dir().join("gmc_test_init_state_dedup.yaml");
+    let path = path.to_string_lossy().to_string();
+    let (a, b) = (AnyKey::from(rdev::Key::KeyA), AnyKey::from(rdev::Key::KeyB));
+    let mut record = Recorder::default();
+    record.init_state.pressed_keys = vec![a.clone(), b.clone(), a.clone(), b.clone(), a.clone()];
+    record.save_to_file(path.clone());
+    let loaded = Recorder::from_file(path.clone());
+    assert_eq!(loaded.init_state.pressed_keys, [a.clone(), b.clone()]);
+
+    // kept as saved if asked to
+    record.config.raw_init_state = true;
+    record.save_to_file(path.clone());
+    let loaded = Recorder::from_file(path.clone());
+    assert_eq!(
+        loaded.init_state.pressed_keys,
+        record.init_state.pressed_keys
+    );
+    std::fs::remove_file(path).unwrap();
+
+    // a kept new recording starts from the state at its start
+    let mut record = Recorder::default();
+    record.recorder.pressed_keys.push(b.clone());
+    record.start_record(0);
+    record.stop_record(false);
+    assert_eq!(record.init_state.pressed_keys, record.recorder.pressed_keys);
+    record.recorder.pressed_keys.clear();
+    record.start_record(0);
+    record.stop_record(true);
+    assert_eq!(record.init_state.pressed_keys, [b]);
+}
+
+#[test]
+fn test_wheel_notch_size() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut record = Recorder {
+        recv: Some(rx),
+        ..Default::default()
+    };
+    record.config.wheel_notch_size = 120.0;
+    record.start_record(0);
+    // less than half a notch is kept for the next delta
+    for (i, dy) in [50, 50, 50, 250].into_iter().enumerate() {
+        let ev = rdev::EventType::Wheel {
+            delta_x: 0,
+            delta_y: dy,
+        };
+        tx.send(CallbackType::MK(i as f64 * 10.0, ev, String::new()))
+            .unwrap();
+        record.listen();
+    }
+    let ev = rdev::EventType::MouseMove { x: 0.0, y: 0.0 };
+    tx.send(CallbackType::MK(50.0, ev, String::new())).unwrap();
+    record.listen();
+    let wheels: Vec<_> = record
+        .records
+        .iter()
+        .flat_map(|r| r.moves.iter())
+        .filter(|m| matches!(m, AnyOffset::Wheel(..)))
+        .cloned()
+        .collect();
+    assert_eq!(
+        wheels,
+        vec![AnyOffset::Wheel(0.0, 1.0), AnyOffset::Wheel(0.0, 2.0)]
+    );
+}
+
+#[test]
+fn test_self_test() {
+    struct MockProbe(bool);
+    impl SelfTestProbe for MockProbe {
+        fn connect_vigem(&self) -> Result<(), String> {
+            Err("Cannot connect to ViGEmBus".to_string())
+        }
+        fn load_xinput(&self) -> Result<(), String> {
+            match self.0 {
+                true => Ok(()),
+                false => Err("Cannot load XInput".to_string()),
+            }
+        }
+        fn open_display(&self) -> Result<(), String> {
+            Err("No display".to_string())
+        }
+    }
+    let mut record = Recorder::default();
+    let report = record.self_test_with(&MockProbe(true));
+    assert_eq!(report.vigem, Err("Cannot connect to ViGEmBus".to_string()));
+    assert_eq!(report.xinput, Ok(()));
+    assert_eq!(report.listener, Err("No display".to_string()));
+    assert_eq!(report.playback, Ok(()));
+    assert!(!report.is_ok());
+    assert_eq!(record.config.enable_controller, [true, false, false, false]);
+
+    // the controllers are turned off before `init` without XInput
+    let report = record.self_test_with(&MockProbe(false));
+    assert!(report.xinput.is_err());
+    assert_eq!(record.config.enable_controller, [false; 4]);
+    assert!(report.to_string().contains("XInput: Cannot load XInput"));
+
+    // a listener failing to start stops the recorder
+    let (tx, rx) = std::sync::mpsc::channel();
+    record.recv = Some(rx);
+    tx.send(CallbackType::ListenerFailed(
+        "Cannot create listener".to_string(),
+    ))
+    .unwrap();
+    record.listen();
+    assert!(!record.is_ok());
+}
@@ -1,5 +1,5 @@
 use crate::recorder::RecordEntry;
-use log::debug;
+use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
@@ -12,6 +12,8 @@ pub struct GlobalState {
     #[serde(skip)]
     rec_pressed: Vec<AnyKey>,
     #[serde(skip)]
+    rec_repeated: Vec<AnyKey>,
+    #[serde(skip)]
     rec_released: Vec<AnyKey>,
     #[serde(skip)]
     rec_moves: Vec<AnyOffset>,
@@ -46,8 +48,24 @@ impl GlobalState {
         self.rec_pressed.push(key.clone());
         if !self.pressed_keys.contains(&key) {
             self.pressed_keys.push(key);
+        } else if matches!(key, AnyKey::Keyboard(_)) {
+            // still held, an auto-repeat
+            self.rec_repeated.push(key);
         }
     }
+    /// Remove the repeated pressed keys, keeping the first press. Returns the removed ones.
+    pub fn dedup_pressed(&mut self) -> Vec<AnyKey> {
+        let mut kept: Vec<AnyKey> = Vec::new();
+        let mut removed = Vec::new();
+        for key in self.pressed_keys.drain(..) {
+            match kept.contains(&key) {
+                true => removed.push(key),
+                false => kept.push(key),
+            }
+        }
+        self.pressed_keys = kept;
+        removed
+    }
     pub fn key_up(&mut self, key: AnyKey) {
         self.pressed_keys.retain(|k| k != &key);
         self.rec_released.push(key);
@@ -55,8 +73,12 @@ impl GlobalState {
     pub fn moves(&mut self, offset: AnyOffset) {
         match offset {
             AnyOffset::Mouse(x, y) => self.offsets.mouse = (x, y),
+            // no absolute position
+            AnyOffset::MouseDelta(..) => (),
             AnyOffset::Wheel(x, y) => self.offsets.wheel = (x, y),
             AnyOffset::Trigger(i, x, y) => self.offsets.trigger[i as usize] = (x, y),
+            AnyOffset::LeftTrigger(i, x) => self.offsets.trigger[i as usize].0 = x,
+            AnyOffset::RightTrigger(i, y) => self.offsets.trigger[i as usize].1 = y,
             AnyOffset::LeftStick(i, x, y) => self.offsets.left_stick[i as usize] = (x, y),
             AnyOffset::RightStick(i, x, y) => self.offsets.right_stick[i as usize] = (x, y),
         }
@@ -67,17 +89,21 @@ impl GlobalState {
         let pressed = std::mem::replace(&mut self.rec_pressed, Vec::new());
         let released = std::mem::replace(&mut self.rec_released, Vec::new());
         let moves = std::mem::replace(&mut self.rec_moves, Vec::new());
+        let repeated = std::mem::take(&mut self.rec_repeated);
         let res = RecordEntry {
             ms: ms - self.rec_start_ms,
             pressed,
             released,
             moves,
+            repeated,
+            ..Default::default()
         };
         self.time_ms = ms;
         res
     }
     pub fn clear_this(&mut self) {
         self.rec_pressed.clear();
+        self.rec_repeated.clear();
         self.rec_released.clear();
         self.rec_moves.clear();
     }
@@ -88,14 +114,35 @@ impl GlobalState {
     /// - The Short::key field will be the last key pressed.
     /// - The Short::controller_btn_option field will be the num of controller button pressed.
     /// - The Short::controller_btn field will be the last controller button pressed.
-    pub fn get_pattern(&self) -> ShortCut {
+    pub fn get_pattern(&self, distinguish_lr_modifiers: bool) -> ShortCut {
         let mut res = ShortCut::ANY;
         for key in &self.pressed_keys {
             match key {
                 AnyKey::Keyboard(Key(k)) => match k {
-                    rdev::Key::ControlLeft | rdev::Key::ControlRight => res.ctrl = Some(true),
-                    rdev::Key::Alt | rdev::Key::AltGr => res.alt = Some(true),
-                    rdev::Key::ShiftLeft | rdev::Key::ShiftRight => res.shift = Some(true),
+                    rdev::Key::ControlLeft => {
+                        res.ctrl = Some(true);
+                        res.ctrl_left = Some(true);
+                    }
+                    rdev::Key::ControlRight => {
+                        res.ctrl = Some(true);
+                        res.ctrl_right = Some(true);
+                    }
+                    rdev::Key::Alt => {
+                        res.alt = Some(true);
+                        res.alt_left = Some(true);
+                    }
+                    rdev::Key::AltGr => {
+                        res.alt = Some(true);
+                        res.alt_right = Some(true);
+                    }
+                    rdev::Key::ShiftLeft => {
+                        res.shift = Some(true);
+                        res.shift_left = Some(true);
+                    }
+                    rdev::Key::ShiftRight => {
+                        res.shift = Some(true);
+                        res.shift_right = Some(true);
+                    }
                     rdev::Key::Tab => res.tab = Some(true),
                     rdev::Key::MetaLeft | rdev::Key::MetaRight => res.windows = Some(true),
                     k => {
@@ -115,10 +162,15 @@ impl GlobalState {
                 }
             }
         }
+        if !distinguish_lr_modifiers {
+            (res.ctrl_left, res.ctrl_right) = (res.ctrl, res.ctrl);
+            (res.shift_left, res.shift_right) = (res.shift, res.shift);
+            (res.alt_left, res.alt_right) = (res.alt, res.alt);
+        }
         res
     }
 
-    pub fn match_shortcut(&self, pat: &ShortCut, shortcut: &ShortCut) -> bool {
+    pub fn match_shortcut(&self, pat: &ShortCut, shortcut: &ShortCut, stick_deadzone: f64) -> bool {
         // compare mods
         fn cmp(t: &Option<bool>, s: &Option<bool>) -> bool {
             s.is_none() || t.is_some() == s.unwrap()
@@ -128,6 +180,12 @@ impl GlobalState {
             && cmp(&pat.shift, &shortcut.shift)
             && cmp(&pat.tab, &shortcut.tab)
             && cmp(&pat.windows, &shortcut.windows)
+            && cmp(&pat.ctrl_left, &shortcut.ctrl_left)
+            && cmp(&pat.ctrl_right, &shortcut.ctrl_right)
+            && cmp(&pat.shift_left, &shortcut.shift_left)
+            && cmp(&pat.shift_right, &shortcut.shift_right)
+            && cmp(&pat.alt_left, &shortcut.alt_left)
+            && cmp(&pat.alt_right, &shortcut.alt_right)
             && cmp(&pat.mouse_l_button, &shortcut.mouse_l_button)
             && cmp(&pat.mouse_r_button, &shortcut.mouse_r_button)
             && cmp(&pat.mouse_m_button, &shortcut.mouse_m_button);
@@ -145,6 +203,17 @@ impl GlobalState {
                 return false;
             }
         }
+        // compare sticks
+        if let Some((i, dir)) = shortcut.left_stick {
+            if !dir.matches(self.offsets.left_stick[i as usize], stick_deadzone) {
+                return false;
+            }
+        }
+        if let Some((i, dir)) = shortcut.right_stick {
+            if !dir.matches(self.offsets.right_stick[i as usize], stick_deadzone) {
+                return false;
+            }
+        }
         match (shortcut.key_option, &shortcut.key) {
             // skip
             (0, None) => (),
@@ -216,11 +285,16 @@ impl GlobalState {
         true
     }
 
-    pub fn match_shortcuts(&self, pat: &ShortCut, shortcuts: &ShortCuts) -> bool {
+    pub fn match_shortcuts(
+        &self,
+        pat: &ShortCut,
+        shortcuts: &ShortCuts,
+        stick_deadzone: f64,
+    ) -> bool {
         match shortcuts {
             ShortCuts::Contains(vec) => {
                 for shortcut in vec {
-                    if self.match_shortcut(pat, shortcut) {
+                    if self.match_shortcut(pat, shortcut, stick_deadzone) {
                         debug!("Match {:?} found matched: {:?}", shortcuts, shortcut);
                         return true;
                     }
@@ -230,7 +304,7 @@ impl GlobalState {
             }
             ShortCuts::Exclude(vec) => {
                 for shortcut in vec {
-                    if self.match_shortcut(pat, shortcut) {
+                    if self.match_shortcut(pat, shortcut, stick_deadzone) {
                         debug!("Match {:?} with matched: {:?}", shortcuts, shortcut);
                         return false;
                     }
@@ -244,6 +318,10 @@ impl GlobalState {
     pub fn start_rec(&mut self, time_offset: f64) {
         self.rec_start_ms = self.time_ms - time_offset;
     }
+    /// Leave out the time from the last entry to ms from the recording, except for gap_ms.
+    pub fn skip_rec(&mut self, ms: f64, gap_ms: f64) {
+        self.rec_start_ms += (ms - self.time_ms - gap_ms).max(0.0);
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -256,6 +334,9 @@ impl Key {
     pub fn release(&self) -> rdev::EventType {
         rdev::EventType::KeyRelease(self.0.clone())
     }
+    pub fn rdev_key(&self) -> rdev::Key {
+        self.0
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq)]
@@ -282,12 +363,62 @@ pub struct ShortCut {
     pub shift: Option<bool>,
     pub tab: Option<bool>,
     pub windows: Option<bool>,
+    /// Side specific modifiers, only told apart with `distinguish_lr_modifiers`,
+    /// otherwise both sides follow the combined modifier.
+    #[serde(default)]
+    pub ctrl_left: Option<bool>,
+    #[serde(default)]
+    pub ctrl_right: Option<bool>,
+    #[serde(default)]
+    pub shift_left: Option<bool>,
+    #[serde(default)]
+    pub shift_right: Option<bool>,
+    #[serde(default)]
+    pub alt_left: Option<bool>,
+    #[serde(default)]
+    pub alt_right: Option<bool>,
     pub mouse_l_button: Option<bool>,
     pub mouse_r_button: Option<bool>,
     pub mouse_m_button: Option<bool>,
     // trigger on the stick of the id'th controller
     pub trigger_l: Option<u32>,
     pub trigger_r: Option<u32>,
+    /// stick of the id'th controller pushed in the direction, beyond the deadzone
+    #[serde(default)]
+    pub left_stick: Option<(u32, StickDir)>,
+    #[serde(default)]
+    pub right_stick: Option<(u32, StickDir)>,
+}
+
+/// Direction a stick is pushed in, for shortcuts.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum StickDir {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// (degrees, magnitude) counterclockwise from right, matched within 22.5 degrees
+    /// and when pushed at least by the magnitude
+    Angle(f64, f64),
+}
+
+impl StickDir {
+    /// The stick at (x, y) points in this direction, and is out of the deadzone.
+    pub fn matches(&self, (x, y): (f64, f64), deadzone: f64) -> bool {
+        let magnitude = x.hypot(y);
+        if magnitude <= deadzone {
+            return false;
+        }
+        let (angle, within, least) = match *self {
+            StickDir::Right => (0.0, 45.0, 0.0),
+            StickDir::Up => (90.0, 45.0, 0.0),
+            StickDir::Left => (180.0, 45.0, 0.0),
+            StickDir::Down => (270.0, 45.0, 0.0),
+            StickDir::Angle(angle, least) => (angle, 22.5, least),
+        };
+        let diff = (y.atan2(x).to_degrees() - angle).rem_euclid(360.0);
+        magnitude >= least && diff.min(360.0 - diff) <= within
+    }
 }
 
 /// A list of shortcuts that can be used to trigger an action.
@@ -298,6 +429,13 @@ pub enum ShortCuts {
     Exclude(Vec<ShortCut>),
 }
 
+impl Default for ShortCuts {
+    /// matches nothing
+    fn default() -> Self {
+        ShortCuts::Contains(vec![])
+    }
+}
+
 #[allow(unused)]
 /// this is implement of basic keybindings
 impl ShortCut {
@@ -311,11 +449,19 @@ impl ShortCut {
         shift: Some(true),
         tab: Some(false),
         windows: Some(false),
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: None,
         mouse_r_button: None,
         mouse_m_button: None,
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
     pub const CTRL_SHIFT_ENTER: Self = Self {
         key_option: 0,
@@ -327,11 +473,19 @@ impl ShortCut {
         shift: Some(true),
         tab: Some(false),
         windows: Some(false),
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: None,
         mouse_r_button: None,
         mouse_m_button: None,
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
     pub const ESCAPE: Self = Self {
         key_option: 0,
@@ -343,11 +497,19 @@ impl ShortCut {
         shift: Some(false),
         tab: Some(false),
         windows: Some(false),
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: None,
         mouse_r_button: None,
         mouse_m_button: None,
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
     pub const SHIFT_ESCAPE: Self = Self {
         key_option: 0,
@@ -359,11 +521,19 @@ impl ShortCut {
         shift: Some(true),
         tab: Some(false),
         windows: Some(false),
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: None,
         mouse_r_button: None,
         mouse_m_button: None,
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
     pub const CTRL_ESCAPE: Self = Self {
         key_option: 0,
@@ -375,11 +545,19 @@ impl ShortCut {
         shift: Some(false),
         tab: Some(false),
         windows: Some(false),
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: None,
         mouse_r_button: None,
         mouse_m_button: None,
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
     pub const CTRL_ENTER: Self = Self {
         key_option: 0,
@@ -391,11 +569,19 @@ impl ShortCut {
         shift: Some(false),
         tab: Some(false),
         windows: Some(false),
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: None,
         mouse_r_button: None,
         mouse_m_button: None,
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
     pub const CTRL_RIGHT_S: Self = Self {
         key_option: 0,
@@ -407,11 +593,19 @@ impl ShortCut {
         shift: Some(true),
         tab: Some(false),
         windows: Some(false),
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: None,
         mouse_r_button: Some(true),
         mouse_m_button: None,
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
 
     /// Any key is matched
@@ -425,11 +619,19 @@ impl ShortCut {
         shift: None,
         tab: None,
         windows: None,
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: None,
         mouse_r_button: None,
         mouse_m_button: None,
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
     /// Any key should not pressed
     pub const NONE: Self = Self {
@@ -442,11 +644,19 @@ impl ShortCut {
         shift: Some(false),
         tab: Some(false),
         windows: Some(false),
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: Some(false),
         mouse_r_button: Some(false),
         mouse_m_button: Some(false),
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
     /// Any key should not pressed, except modifiers
     pub const EMPTY: Self = Self {
@@ -459,11 +669,19 @@ impl ShortCut {
         shift: None,
         tab: None,
         windows: None,
+        ctrl_left: None,
+        ctrl_right: None,
+        shift_left: None,
+        shift_right: None,
+        alt_left: None,
+        alt_right: None,
         mouse_l_button: Some(false),
         mouse_r_button: Some(false),
         mouse_m_button: Some(false),
         trigger_l: None,
         trigger_r: None,
+        left_stick: None,
+        right_stick: None,
     };
 
     pub fn key(key: rdev::Key) -> Self {
@@ -477,11 +695,19 @@ impl ShortCut {
             shift: Some(false),
             tab: Some(false),
             windows: Some(false),
+            ctrl_left: None,
+            ctrl_right: None,
+            shift_left: None,
+            shift_right: None,
+            alt_left: None,
+            alt_right: None,
             mouse_l_button: Some(false),
             mouse_r_button: Some(false),
             mouse_m_button: Some(false),
             trigger_l: None,
             trigger_r: None,
+            left_stick: None,
+            right_stick: None,
         }
     }
     pub fn alt(key: rdev::Key) -> Self {
@@ -495,11 +721,19 @@ impl ShortCut {
             shift: Some(false),
             tab: Some(false),
             windows: Some(false),
+            ctrl_left: None,
+            ctrl_right: None,
+            shift_left: None,
+            shift_right: None,
+            alt_left: None,
+            alt_right: None,
             mouse_l_button: Some(false),
             mouse_r_button: Some(false),
             mouse_m_button: Some(false),
             trigger_l: None,
             trigger_r: None,
+            left_stick: None,
+            right_stick: None,
         }
     }
     pub fn ctrl_alt(key: rdev::Key) -> Self {
@@ -513,11 +747,19 @@ impl ShortCut {
             shift: Some(false),
             tab: Some(false),
             windows: Some(false),
+            ctrl_left: None,
+            ctrl_right: None,
+            shift_left: None,
+            shift_right: None,
+            alt_left: None,
+            alt_right: None,
             mouse_l_button: Some(false),
             mouse_r_button: Some(false),
             mouse_m_button: Some(false),
             trigger_l: None,
             trigger_r: None,
+            left_stick: None,
+            right_stick: None,
         }
     }
     pub fn shift_alt(key: rdev::Key) -> Self {
@@ -531,11 +773,19 @@ impl ShortCut {
             shift: Some(true),
             tab: Some(false),
             windows: Some(false),
+            ctrl_left: None,
+            ctrl_right: None,
+            shift_left: None,
+            shift_right: None,
+            alt_left: None,
+            alt_right: None,
             mouse_l_button: Some(false),
             mouse_r_button: Some(false),
             mouse_m_button: Some(false),
             trigger_l: None,
             trigger_r: None,
+            left_stick: None,
+            right_stick: None,
         }
     }
 }
@@ -567,6 +817,20 @@ impl Debug for ShortCut {
             Some(false) => write!(f, "!Windows + ")?,
             None => (),
         }
+        for (name, side) in [
+            ("LCtrl", self.ctrl_left),
+            ("RCtrl", self.ctrl_right),
+            ("LShift", self.shift_left),
+            ("RShift", self.shift_right),
+            ("LAlt", self.alt_left),
+            ("RAlt", self.alt_right),
+        ] {
+            match side {
+                Some(true) => write!(f, "{name} + ")?,
+                Some(false) => write!(f, "!{name} + ")?,
+                None => (),
+            }
+        }
         match self.mouse_l_button {
             Some(true) => write!(f, "MouseLeft + ")?,
             Some(false) => write!(f, "!MouseLeft + ")?,
@@ -590,6 +854,12 @@ impl Debug for ShortCut {
             Some(v) => write!(f, "TriggerR({}) + ", v)?,
             None => (),
         }
+        if let Some((id, dir)) = self.left_stick {
+            write!(f, "LeftStick({}, {:?}) + ", id, dir)?;
+        }
+        if let Some((id, dir)) = self.right_stick {
+            write!(f, "RightStick({}, {:?}) + ", id, dir)?;
+        }
         match (self.key_option, &self.key) {
             (0, None) => write!(f, "SkipKey")?,
             (1, None) => write!(f, "AnyKey")?,
@@ -627,11 +897,17 @@ pub enum AnyKey {
 pub enum AnyOffset {
     /// Any offset on the mouse, f64 is the offset value, respectively x and y
     Mouse(f64, f64),
+    /// Relative mouse movement of camera mode, f64 is the delta, respectively x and y
+    MouseDelta(f64, f64),
     /// Any offset on the mouse wheel, f64 is the offset value, respectively x and y
     Wheel(f64, f64),
     /// Any offset on the controller, (u32, f64, f64) is the controller id and offsets
     /// respectively LeftTrigger, RightTrigger
     Trigger(u32, f64, f64),
+    /// Left trigger alone, (u32, f64) is the controller id and offset, the right one is kept
+    LeftTrigger(u32, f64),
+    /// Right trigger alone, (u32, f64) is the controller id and offset, the left one is kept
+    RightTrigger(u32, f64),
     /// Any offset on the controller, (u32, f64, f64) is the controller id and offset x, y
     LeftStick(u32, f64, f64),
     /// Any offset on the controller, (u32, f64, f64) is the controller id and offset x, y
@@ -652,32 +928,135 @@ pub enum ControllerEvent {
     ButtonPress(usize),
     ButtonRelease(usize),
     TriggerMove(f64, f64),
+    LeftTriggerMove(f64),
+    RightTriggerMove(f64),
     LSticksMove(f64, f64),
     RSticksMove(f64, f64),
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct ControllerRaw {
     pub pack_num: u32,
     pub button: u16,
     pub tri: (u8, u8),
     pub sticker: (i16, i16, i16, i16),
+    /// Scale of the stick magnitude, clamped to 1.0 after scaling.
+    pub stick_sensitivity: f64,
+    /// Store sticks in circular space, see `square_to_circle`.
+    pub circular_normalize: bool,
+    /// Emit only the trigger that changed, instead of both together.
+    pub split_triggers: bool,
+    pub connected: bool,
+}
+
+impl Default for ControllerRaw {
+    fn default() -> Self {
+        Self {
+            pack_num: 0,
+            button: 0,
+            tri: (0, 0),
+            sticker: (0, 0, 0, 0),
+            stick_sensitivity: 1.0,
+            circular_normalize: false,
+            split_triggers: false,
+            connected: false,
+        }
+    }
 }
 
 impl ControllerRaw {
+    #[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+    pub fn new(stick_sensitivity: f64, circular_normalize: bool) -> Self {
+        Self {
+            stick_sensitivity,
+            circular_normalize,
+            ..Default::default()
+        }
+    }
+    fn scale_stick(&self, x: i16, y: i16) -> (f64, f64) {
+        let mut x = x as f64 / i16::MAX as f64;
+        let mut y = y as f64 / i16::MAX as f64;
+        if self.circular_normalize {
+            (x, y) = square_to_circle(x, y);
+        }
+        let len = x.hypot(y);
+        if self.stick_sensitivity == 1.0 || len == 0.0 {
+            return (x, y);
+        }
+        let scale = (len * self.stick_sensitivity).min(1.0) / len;
+        (x * scale, y * scale)
+    }
     pub fn trigger_change(&mut self, tri_l: u8, tri_r: u8) -> ControllerEvent {
         self.tri = (tri_l, tri_r);
         ControllerEvent::TriggerMove(tri_l as f64 / u8::MAX as f64, tri_r as f64 / u8::MAX as f64)
     }
+    /// Events of each trigger that changed, keeping the other one.
+    pub fn split_trigger_change(&mut self, tri_l: u8, tri_r: u8) -> Vec<ControllerEvent> {
+        let mut res = Vec::new();
+        if tri_l != self.tri.0 {
+            res.push(ControllerEvent::LeftTriggerMove(
+                tri_l as f64 / u8::MAX as f64,
+            ));
+        }
+        if tri_r != self.tri.1 {
+            res.push(ControllerEvent::RightTriggerMove(
+                tri_r as f64 / u8::MAX as f64,
+            ));
+        }
+        self.tri = (tri_l, tri_r);
+        res
+    }
     pub fn sl_change(&mut self, l_x: i16, l_y: i16) -> ControllerEvent {
         self.sticker.0 = l_x;
         self.sticker.1 = l_y;
-        ControllerEvent::LSticksMove(l_x as f64 / i16::MAX as f64, l_y as f64 / i16::MAX as f64)
+        let (x, y) = self.scale_stick(l_x, l_y);
+        ControllerEvent::LSticksMove(x, y)
     }
     pub fn sr_change(&mut self, r_x: i16, r_y: i16) -> ControllerEvent {
         self.sticker.2 = r_x;
         self.sticker.3 = r_y;
-        ControllerEvent::RSticksMove(r_x as f64 / i16::MAX as f64, r_y as f64 / i16::MAX as f64)
+        let (x, y) = self.scale_stick(r_x, r_y);
+        ControllerEvent::RSticksMove(x, y)
+    }
+    /// Events of the changes since last poll, `None` if the controller is disconnected.
+    ///
+    /// On disconnecting, everything held is released as if the controller was put down.
+    pub fn poll(&mut self, pad: Option<&PadState>) -> Vec<ControllerEvent> {
+        let neutral = PadState::default();
+        let pad = match pad {
+            Some(pad) if self.connected && pad.packet == self.pack_num => return Vec::new(),
+            Some(pad) => {
+                if !self.connected {
+                    info!("Controller connected.");
+                    self.connected = true;
+                }
+                pad
+            }
+            None if self.connected => {
+                info!("Controller disconnected.");
+                self.connected = false;
+                &neutral
+            }
+            None => return Vec::new(),
+        };
+        self.pack_num = pad.packet;
+        let mut res = Vec::new();
+        if pad.trigger != self.tri && self.split_triggers {
+            res.append(&mut self.split_trigger_change(pad.trigger.0, pad.trigger.1));
+        } else if pad.trigger != self.tri {
+            res.push(self.trigger_change(pad.trigger.0, pad.trigger.1));
+        }
+        if pad.left_stick != (self.sticker.0, self.sticker.1) {
+            res.push(self.sl_change(pad.left_stick.0, pad.left_stick.1));
+        }
+        if pad.right_stick != (self.sticker.2, self.sticker.3) {
+            res.push(self.sr_change(pad.right_stick.0, pad.right_stick.1));
+        }
+        if pad.buttons != self.button {
+            res.append(&mut self.btn_change(pad.buttons));
+        }
+        res
     }
     pub fn btn_change(&mut self, mut btn: u16) -> Vec<ControllerEvent> {
         let mut old = self.button;
@@ -699,3 +1078,388 @@ impl ControllerRaw {
         res
     }
 }
+
+/// Map a stick value from the square range of XInput onto the unit circle.
+///
+/// Uses the elliptical grid mapping `u = x * sqrt(1 - y^2 / 2)`, `v = y * sqrt(1 - x^2 / 2)`,
+/// so edges of the square land on the circle, e.g. the corner (1, 1) becomes
+/// (0.707, 0.707), while the axes are unchanged.
+#[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+pub fn square_to_circle(x: f64, y: f64) -> (f64, f64) {
+    let (x, y) = (x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0));
+    (
+        x * (1.0 - y * y / 2.0).sqrt(),
+        y * (1.0 - x * x / 2.0).sqrt(),
+    )
+}
+
+/// Inverse of `square_to_circle`, values outside the unit circle are pulled onto it first.
+///
+/// `x = (sqrt(2 + u^2 - v^2 + 2 sqrt(2) u) - sqrt(2 + u^2 - v^2 - 2 sqrt(2) u)) / 2`,
+/// and y likewise with u and v swapped.
+pub fn circle_to_square(u: f64, v: f64) -> (f64, f64) {
+    let len = u.hypot(v);
+    let (u, v) = if len > 1.0 {
+        (u / len, v / len)
+    } else {
+        (u, v)
+    };
+    let r8 = 2.0 * std::f64::consts::SQRT_2;
+    let (uu, vv) = (u * u, v * v);
+    let x =
+        ((2.0 + uu - vv + r8 * u).max(0.0).sqrt() - (2.0 + uu - vv - r8 * u).max(0.0).sqrt()) / 2.0;
+    let y =
+        ((2.0 - uu + vv + r8 * v).max(0.0).sqrt() - (2.0 - uu + vv - r8 * v).max(0.0).sqrt()) / 2.0;
+    (x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0))
+}
+
+/// Raw state of a controller at one poll.
+#[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct PadState {
+    pub packet: u32,
+    pub buttons: u16,
+    pub trigger: (u8, u8),
+    pub left_stick: (i16, i16),
+    pub right_stick: (i16, i16),
+}
+
+/// Source of controller states, `None` if the controller is not connected.
+#[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+pub trait PadSource {
+    fn pad_state(&self, id: u32) -> Option<PadState>;
+}
+
+/// Poll the controllers in use, connectivity is checked every time
+/// so that controllers connected during the session are picked up.
+#[cfg_attr(not(all(windows, feature = "controller")), allow(dead_code))]
+pub fn poll_controllers(
+    source: &impl PadSource,
+    uses: &[u32],
+    controllers: &mut [ControllerRaw],
+) -> Vec<(u32, ControllerEvent)> {
+    let mut res = Vec::new();
+    for &i in uses {
+        let pad = source.pad_state(i);
+        for ev in controllers[i as usize].poll(pad.as_ref()) {
+            res.push((i, ev));
+        }
+    }
+    res
+}
+
+/// Remaps recorded mouse input onto a controller for playback.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MouseToControllerMap {
+    /// The id of the controller to drive.
+    pub controller: u32,
+    /// (mouse button, controller button) pairs, unmapped mouse buttons are kept.
+    pub buttons: Vec<(u32, usize)>,
+    /// Right stick deflection per pixel of mouse movement,
+    /// `None` to keep the mouse movement.
+    pub stick_sensitivity: Option<f64>,
+}
+
+impl MouseToControllerMap {
+    /// Remap the records entry by entry, so positions in the result match the original.
+    ///
+    /// Mouse movement between two entries becomes a right stick deflection,
+    /// which is returned to center on the first entry without movement.
+    pub fn apply(&self, records: &[RecordEntry]) -> Vec<RecordEntry> {
+        let map_key = |key: &AnyKey| match key {
+            AnyKey::MouseButton(btn) => match self.buttons.iter().find(|(b, _)| b == btn) {
+                Some((_, code)) => AnyKey::Controller(self.controller, *code),
+                None => key.clone(),
+            },
+            key => key.clone(),
+        };
+        let mut last_pos = None;
+        let mut deflected = false;
+        let mut res = Vec::with_capacity(records.len());
+        for record in records {
+            let mut moves = Vec::with_capacity(record.moves.len());
+            let mut stick = None;
+            for offset in &record.moves {
+                match (offset, self.stick_sensitivity) {
+                    (AnyOffset::Mouse(x, y), Some(sensitivity)) => {
+                        let (lx, ly) = last_pos.unwrap_or((*x, *y));
+                        last_pos = Some((*x, *y));
+                        let (sx, sy) = stick.unwrap_or((0.0, 0.0));
+                        // screen y grows downwards, stick y grows upwards
+                        stick = Some((sx + (x - lx) * sensitivity, sy - (y - ly) * sensitivity));
+                    }
+                    (AnyOffset::MouseDelta(dx, dy), Some(sensitivity)) => {
+                        let (sx, sy) = stick.unwrap_or((0.0, 0.0));
+                        stick = Some((sx + dx * sensitivity, sy - dy * sensitivity));
+                    }
+                    _ => moves.push(offset.clone()),
+                }
+            }
+            match stick {
+                Some((x, y)) => {
+                    let (x, y) = (x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0));
+                    moves.push(AnyOffset::RightStick(self.controller, x, y));
+                    deflected = true;
+                }
+                None if deflected => {
+                    moves.push(AnyOffset::RightStick(self.controller, 0.0, 0.0));
+                    deflected = false;
+                }
+                None => (),
+            }
+            res.push(RecordEntry {
+                ms: record.ms,
+                pressed: record.pressed.iter().map(map_key).collect(),
+                released: record.released.iter().map(map_key).collect(),
+                moves,
+                wait: record.wait,
+                repeated: record.repeated.clone(),
+            });
+        }
+        res
+    }
+}
+
+#[test]
+fn test_mouse_to_controller() {
+    let map = MouseToControllerMap {
+        controller: 0,
+        buttons: vec![(0, 0x1000)],
+        stick_sensitivity: Some(0.01),
+    };
+    let records = vec![
+        RecordEntry {
+            ms: 0.0,
+            pressed: vec![AnyKey::MouseButton(0), AnyKey::MouseButton(2)],
+            moves: vec![AnyOffset::Mouse(100.0, 100.0)],
+            ..Default::default()
+        },
+        RecordEntry {
+            ms: 10.0,
+            released: vec![AnyKey::MouseButton(0)],
+            moves: vec![AnyOffset::Mouse(150.0, 80.0)],
+            ..Default::default()
+        },
+        RecordEntry {
+            ms: 20.0,
+            ..Default::default()
+        },
+    ];
+    let res = map.apply(&records);
+    assert_eq!(res.len(), records.len());
+    assert_eq!(
+        res[0].pressed,
+        vec![AnyKey::Controller(0, 0x1000), AnyKey::MouseButton(2)]
+    );
+    assert_eq!(res[1].released, vec![AnyKey::Controller(0, 0x1000)]);
+    assert_eq!(res[0].moves, vec![AnyOffset::RightStick(0, 0.0, 0.0)]);
+    assert_eq!(res[1].moves, vec![AnyOffset::RightStick(0, 0.5, 0.2)]);
+    assert_eq!(res[2].moves, vec![AnyOffset::RightStick(0, 0.0, 0.0)]);
+}
+
+#[test]
+fn test_stick_sensitivity() {
+    let mut raw = ControllerRaw::new(0.5, false);
+    assert_eq!(
+        raw.sl_change(i16::MAX, 0),
+        ControllerEvent::LSticksMove(0.5, 0.0)
+    );
+    assert_eq!(raw.sticker.0, i16::MAX);
+    match raw.sr_change(0, i16::MIN + 1) {
+        ControllerEvent::RSticksMove(x, y) => {
+            assert_eq!(x, 0.0);
+            assert!((y + 0.5).abs() < 1e-9);
+        }
+        ev => panic!("unexpected {:?}", ev),
+    }
+    // scaled magnitude is clamped to 1.0
+    let mut raw = ControllerRaw::new(2.0, false);
+    match raw.sl_change(i16::MAX, i16::MAX) {
+        ControllerEvent::LSticksMove(x, y) => assert!((x.hypot(y) - 1.0).abs() < 1e-9),
+        ev => panic!("unexpected {:?}", ev),
+    }
+}
+
+#[test]
+fn test_controller_connect() {
+    struct MockPads(std::cell::RefCell<[Option<PadState>; 4]>);
+    impl PadSource for MockPads {
+        fn pad_state(&self, id: u32) -> Option<PadState> {
+            self.0.borrow()[id as usize].clone()
+        }
+    }
+    let pads = MockPads(Default::default());
+    let mut controllers = vec![ControllerRaw::default(); 4];
+    let uses = [0, 1];
+    assert!(poll_controllers(&pads, &uses, &mut controllers).is_empty());
+
+    // controller 1 connects mid-session with A pressed
+    pads.0.borrow_mut()[1] = Some(PadState {
+        packet: 1,
+        buttons: 0x1000,
+        ..Default::default()
+    });
+    assert_eq!(
+        poll_controllers(&pads, &uses, &mut controllers),
+        vec![(1, ControllerEvent::ButtonPress(0x1000))]
+    );
+    assert!(controllers[1].connected);
+    // same packet, nothing changed
+    assert!(poll_controllers(&pads, &uses, &mut controllers).is_empty());
+    // release everything held on disconnecting
+    pads.0.borrow_mut()[1] = None;
+    assert_eq!(
+        poll_controllers(&pads, &uses, &mut controllers),
+        vec![(1, ControllerEvent::ButtonRelease(0x1000))]
+    );
+    assert!(!controllers[1].connected);
+    // not enabled controllers are never polled
+    pads.0.borrow_mut()[2] = Some(PadState {
+        packet: 1,
+        buttons: 0x1000,
+        ..Default::default()
+    });
+    assert!(poll_controllers(&pads, &uses, &mut controllers).is_empty());
+}
+
+#[test]
+fn test_circular_normalize() {
+    let mut raw = ControllerRaw::new(1.0, true);
+    match raw.sl_change(i16::MAX, i16::MAX) {
+        ControllerEvent::LSticksMove(x, y) => {
+            assert!((x.hypot(y) - 1.0).abs() < 1e-9);
+            assert!((x - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-9);
+        }
+        ev => panic!("unexpected {:?}", ev),
+    }
+    // axes are unchanged
+    assert_eq!(
+        raw.sr_change(i16::MAX, 0),
+        ControllerEvent::RSticksMove(1.0, 0.0)
+    );
+    for (x, y) in [(1.0, 1.0), (-1.0, 0.5), (0.3, -0.8), (0.0, 0.0)] {
+        let (u, v) = square_to_circle(x, y);
+        assert!(u.hypot(v) <= 1.0 + 1e-9);
+        let (rx, ry) = circle_to_square(u, v);
+        assert!((rx - x).abs() < 1e-6 && (ry - y).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_lr_modifiers() {
+    let shortcut = ShortCut {
+        ctrl: Some(true),
+        ctrl_right: Some(true),
+        ..ShortCut::key(rdev::Key::KeyS)
+    };
+    let matches = |ctrl, distinguish| {
+        let mut state = GlobalState::default();
+        state.key_down(AnyKey::from(ctrl));
+        state.key_down(AnyKey::from(rdev::Key::KeyS));
+        let pat = state.get_pattern(distinguish);
+        state.match_shortcut(&pat, &shortcut, 0.5)
+    };
+    assert!(matches(rdev::Key::ControlRight, true));
+    assert!(!matches(rdev::Key::ControlLeft, true));
+    // combined by default
+    assert!(matches(rdev::Key::ControlLeft, false));
+    assert!(matches(rdev::Key::ControlRight, false));
+    // side specific modifiers of the same side do not affect the other one
+    let mut state = GlobalState::default();
+    state.key_down(AnyKey::from(rdev::Key::ShiftLeft));
+    let pat = state.get_pattern(true);
+    assert_eq!(
+        (pat.shift, pat.shift_left, pat.shift_right),
+        (Some(true), Some(true), None)
+    );
+}
+
+#[test]
+fn test_repeated_key() {
+    let mut state = GlobalState::default();
+    let key = AnyKey::from(rdev::Key::KeyA);
+    state.key_down(key.clone());
+    let first = state.next_ms(0.0);
+    assert_eq!(first.pressed, vec![key.clone()]);
+    assert!(first.repeated.is_empty());
+    // auto-repeat while still held
+    state.key_down(key.clone());
+    let second = state.next_ms(30.0);
+    assert_eq!(second.pressed, vec![key.clone()]);
+    assert_eq!(second.repeated, vec![key.clone()]);
+    let yaml = serde_yml::to_string(&second).unwrap();
+    let loaded: RecordEntry = serde_yml::from_str(&yaml).unwrap();
+    assert_eq!(loaded.repeated, vec![key.clone()]);
+    // not a repeat after release
+    state.key_up(key.clone());
+    state.key_down(key.clone());
+    assert!(state.next_ms(60.0).repeated.is_empty());
+}
+
+#[test]
+fn test_split_triggers() {
+    let mut raw = ControllerRaw {
+        split_triggers: true,
+        ..Default::default()
+    };
+    let pad = |packet, trigger| PadState {
+        packet,
+        trigger,
+        ..Default::default()
+    };
+    assert_eq!(
+        raw.poll(Some(&pad(1, (0, 255)))),
+        vec![ControllerEvent::RightTriggerMove(1.0)]
+    );
+    // only the left trigger changes
+    assert_eq!(
+        raw.poll(Some(&pad(2, (51, 255)))),
+        vec![ControllerEvent::LeftTriggerMove(0.2)]
+    );
+    let mut state = GlobalState::default();
+    state.moves(AnyOffset::Trigger(0, 0.0, 1.0));
+    state.moves(AnyOffset::LeftTrigger(0, 0.2));
+    assert_eq!(state.offsets.trigger[0], (0.2, 1.0));
+    state.moves(AnyOffset::RightTrigger(0, 0.5));
+    assert_eq!(state.offsets.trigger[0], (0.2, 0.5));
+    // both together without splitting
+    let mut raw = ControllerRaw::default();
+    raw.poll(Some(&pad(1, (0, 255))));
+    assert_eq!(
+        raw.poll(Some(&pad(2, (51, 255)))),
+        vec![ControllerEvent::TriggerMove(0.2, 1.0)]
+    );
+}
+
+#[test]
+fn test_stick_shortcut() {
+    let shortcut = ShortCut {
+        left_stick: Some((0, StickDir::Up)),
+        ..ShortCut::NONE
+    };
+    let matches = |shortcut: &ShortCut, x, y| {
+        let mut state = GlobalState::default();
+        state.moves(AnyOffset::LeftStick(0, x, y));
+        let pat = state.get_pattern(false);
+        state.match_shortcut(&pat, shortcut, 0.25)
+    };
+    assert!(matches(&shortcut, 0.0, 1.0));
+    assert!(matches(&shortcut, -0.4, 0.6));
+    // drift within the deadzone
+    assert!(!matches(&shortcut, 0.0, 0.2));
+    assert!(!matches(&shortcut, 0.8, 0.4));
+    assert!(!matches(&shortcut, 0.0, -1.0));
+    let shortcut = ShortCut {
+        left_stick: Some((0, StickDir::Angle(315.0, 0.9))),
+        ..ShortCut::NONE
+    };
+    assert!(matches(&shortcut, 0.7, -0.7));
+    assert!(!matches(&shortcut, 0.5, -0.5));
+    assert!(!matches(&shortcut, 1.0, 0.0));
+    let shortcut = ShortCut {
+        right_stick: Some((0, StickDir::Left)),
+        ..ShortCut::NONE
+    };
+    assert!(!matches(&shortcut, -1.0, 0.0));
+    assert!(format!("{:?}", shortcut).contains("RightStick(0, Left) + "));
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// Signals of an external trigger, consumed together with the shortcuts.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum TriggerSignal {
+    /// Start recording when ready.
+    Start,
+    /// Stop recording or playback.
+    Stop,
+    /// Save records to file.
+    Save,
+}
+
+/// External source of start/stop/save signals, like a footswitch.
+pub trait TriggerSource: Debug {
+    /// The next pending signal, should not block.
+    fn poll(&mut self) -> Option<TriggerSignal>;
+}
+
+/// MIDI notes or controllers sending each signal, a note on or a controller value
+/// of at least 64 fires the signal.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MidiTriggerConfig {
+    /// Connect to the first input port whose name contains this.
+    pub port: String,
+    pub start: u8,
+    pub stop: u8,
+    pub save: u8,
+}
+
+#[allow(unused)]
+impl MidiTriggerConfig {
+    /// The signal of a raw MIDI message, if any.
+    pub fn signal(&self, message: &[u8]) -> Option<TriggerSignal> {
+        let &[status, number, value] = message else {
+            return None;
+        };
+        let fired = match status & 0xF0 {
+            // note on
+            0x90 => value > 0,
+            // control change
+            0xB0 => value >= 64,
+            _ => false,
+        };
+        match number {
+            _ if !fired => None,
+            n if n == self.start => Some(TriggerSignal::Start),
+            n if n == self.stop => Some(TriggerSignal::Stop),
+            n if n == self.save => Some(TriggerSignal::Save),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "midi")]
+/// Trigger signals from a MIDI device.
+pub struct MidiTrigger {
+    _conn: midir::MidiInputConnection<()>,
+    recv: std::sync::mpsc::Receiver<TriggerSignal>,
+}
+
+#[cfg(feature = "midi")]
+impl MidiTrigger {
+    pub fn connect(config: MidiTriggerConfig) -> Result<Self, String> {
+        let input = midir::MidiInput::new("game-movement-copy").map_err(|e| e.to_string())?;
+        let port = input
+            .ports()
+            .into_iter()
+            .find(|p| input.port_name(p).is_ok_and(|n| n.contains(&config.port)))
+            .ok_or(format!("No MIDI input port named {}", config.port))?;
+        let (tx, rx) = std::sync::mpsc::channel();
+        let conn = input
+            .connect(
+                &port,
+                "trigger",
+                move |_, message, _| {
+                    if let Some(signal) = config.signal(message) {
+                        let _ = tx.send(signal);
+                    }
+                },
+                (),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            _conn: conn,
+            recv: rx,
+        })
+    }
+}
+
+#[cfg(feature = "midi")]
+impl Debug for MidiTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MidiTrigger")
+    }
+}
+
+#[cfg(feature = "midi")]
+impl TriggerSource for MidiTrigger {
+    fn poll(&mut self) -> Option<TriggerSignal> {
+        self.recv.try_recv().ok()
+    }
+}
+
+#[test]
+fn test_midi_signal() {
+    let config = MidiTriggerConfig {
+        port: String::new(),
+        start: 60,
+        stop: 61,
+        save: 64,
+    };
+    assert_eq!(config.signal(&[0x90, 60, 100]), Some(TriggerSignal::Start));
+    assert_eq!(config.signal(&[0x91, 61, 1]), Some(TriggerSignal::Stop));
+    // note on with zero velocity is a note off
+    assert_eq!(config.signal(&[0x90, 61, 0]), None);
+    assert_eq!(config.signal(&[0xB0, 64, 127]), Some(TriggerSignal::Save));
+    assert_eq!(config.signal(&[0xB0, 64, 0]), None);
+    assert_eq!(config.signal(&[0x80, 60, 100]), None);
+    assert_eq!(config.signal(&[0x90, 62, 100]), None);
+}